@@ -1,23 +1,28 @@
 use isideload::{
-    anisette::remote_v3::RemoteV3AnisetteProvider,
-    auth::apple_account::AppleAccount,
-    dev::{
-        app_ids::{AppIdsApi, ListAppIdsResponse},
-        certificates::{CertificatesApi, DevelopmentCertificate},
-        developer_session::DeveloperSession,
+    auth::apple_account::{AppleAccount, LoginState},
+    dev::developer_session::DeveloperSession,
+    sideload::{
+        SideloaderBuilder,
+        builder::{MaxCertsBehavior, TeamSelection},
+        sideloader::Sideloader,
     },
-    sideload::{SideloaderBuilder, builder::MaxCertsBehavior, sideloader::Sideloader},
-    util::{fs_storage::FsStorage, keyring_storage::KeyringStorage, storage::SideloadingStorage},
 };
-use keyring::Entry;
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::{path::PathBuf, sync::OnceLock, time::Duration};
-use tauri::{AppHandle, Emitter, Listener, Manager, State, Window};
-use tauri_plugin_store::StoreExt;
+use tauri::{AppHandle, State, Window};
 use tracing::{debug, warn};
 
-use crate::sideload::{SideloaderGuard, SideloaderMutex};
+use crate::{
+    anisette::{DEFAULT_SERVER, anisette_provider, server_list, servers_for, with_failover},
+    certificate::{MACHINE_NAME, max_certs_callback},
+    error::IloaderError,
+    metadata::{
+        account_metadata, record_login, remove_metadata, save_ids, saved_ids, update_metadata,
+    },
+    session::{cache_session, clear_session, restore_session},
+    sideload::SideloaderMutex,
+    storage::{delete_password, keyring_available, load_password, save_password, storage},
+    team::team_selection_callback,
+    two_factor::{TwoFactorExchange, complete_two_factor},
+};
 
 #[tauri::command]
 pub async fn login_new(
@@ -28,31 +33,29 @@ pub async fn login_new(
     password: String,
     anisette_server: String,
     save_credentials: bool,
-) -> Result<(), String> {
-    let account = login(&handle, &window, &email, &password, anisette_server).await?;
+    two_factor_timeout: Option<u64>,
+    anisette_fallbacks: Option<Vec<String>>,
+) -> Result<(), IloaderError> {
+    let account = login(
+        &handle,
+        &window,
+        &email,
+        &password,
+        server_list(anisette_server, anisette_fallbacks),
+        two_factor_timeout,
+        save_credentials,
+    )
+    .await?;
     let mut sideloader_guard = sideloader_state.lock().unwrap();
-    *sideloader_guard = Some(account);
+    sideloader_guard.insert(email.to_lowercase(), account);
 
     if save_credentials {
-        let pass_entry = Entry::new("iloader", &email)
-            .map_err(|e| format!("Failed to create keyring entry for credentials: {:?}.", e))?;
-        pass_entry
-            .set_password(&password)
-            .map_err(|e| format!("Failed to save credentials to keyring: {:?}", e))?;
-        let store = handle
-            .store("data.json")
-            .map_err(|e| format!("Failed to get store: {:?}", e))?;
-        let mut existing_ids = store
-            .get("ids")
-            .unwrap_or_else(|| Value::Array(vec![]))
-            .as_array()
-            .cloned()
-            .unwrap_or_else(std::vec::Vec::new);
-        let value = Value::String(email.clone());
-        if !existing_ids.contains(&value) {
-            existing_ids.push(value);
+        save_password(&handle, &email, &password)?;
+        let mut ids = saved_ids(&handle);
+        if !ids.contains(&email) {
+            ids.push(email.clone());
+            save_ids(&handle, &ids)?;
         }
-        store.set("ids", Value::Array(existing_ids));
     }
     Ok(())
 }
@@ -64,300 +67,253 @@ pub async fn login_stored(
     email: String,
     anisette_server: String,
     sideloader_state: State<'_, SideloaderMutex>,
-) -> Result<(), String> {
-    let pass_entry = Entry::new("iloader", &email)
-        .map_err(|e| format!("Failed to create keyring entry for credentials: {:?}.", e))?;
-    let password = pass_entry
-        .get_password()
-        .map_err(|e| format!("Failed to get credentials: {:?}", e))?;
-    let account = login(&handle, &window, &email, &password, anisette_server).await?;
+    two_factor_timeout: Option<u64>,
+    anisette_fallbacks: Option<Vec<String>>,
+) -> Result<(), IloaderError> {
+    let password = load_password(&handle, &email)?;
+    let account = login(
+        &handle,
+        &window,
+        &email,
+        &password,
+        server_list(anisette_server, anisette_fallbacks),
+        two_factor_timeout,
+        true,
+    )
+    .await?;
     let mut sideloader_guard = sideloader_state.lock().unwrap();
-    *sideloader_guard = Some(account);
+    sideloader_guard.insert(email.to_lowercase(), account);
 
     Ok(())
 }
 
+// Log in using the cached session if it is still valid, otherwise fall back to a full login with
+// the saved password. Returns whether the cached session was used.
 #[tauri::command]
-pub fn delete_account(handle: AppHandle, email: String) -> Result<(), String> {
-    let pass_entry = Entry::new("iloader", &email)
-        .map_err(|e| format!("Failed to create keyring entry for credentials: {:?}.", e))?;
-    pass_entry
-        .delete_credential()
-        .map_err(|e| format!("Failed to delete credentials: {:?}", e))?;
-    let store = handle
-        .store("data.json")
-        .map_err(|e| format!("Failed to get store: {:?}", e))?;
-    let mut existing_ids = store
-        .get("ids")
-        .unwrap_or_else(|| Value::Array(vec![]))
-        .as_array()
-        .cloned()
-        .unwrap_or_else(std::vec::Vec::new);
-    existing_ids.retain(|v| v.as_str().is_none_or(|s| s != email));
-    store.set("ids", Value::Array(existing_ids));
-    Ok(())
+pub async fn resume_session(
+    handle: AppHandle,
+    window: Window,
+    email: String,
+    anisette_server: String,
+    sideloader_state: State<'_, SideloaderMutex>,
+    two_factor_timeout: Option<u64>,
+    anisette_fallbacks: Option<Vec<String>>,
+) -> Result<bool, IloaderError> {
+    let provider = anisette_provider(&handle, &email, &anisette_server)?;
+    if let Some(dev_session) = restore_session(&handle, &email, provider).await {
+        debug!("Resumed cached session");
+        let account = build_sideloader(&handle, &window, &email, dev_session).await?;
+        let mut sideloader_guard = sideloader_state.lock().unwrap();
+        sideloader_guard.insert(email.to_lowercase(), account);
+        return Ok(true);
+    }
+
+    login_stored(
+        handle,
+        window,
+        email,
+        anisette_server,
+        sideloader_state,
+        two_factor_timeout,
+        anisette_fallbacks,
+    )
+    .await?;
+    Ok(false)
+}
+
+#[tauri::command]
+pub fn delete_account(handle: AppHandle, email: String) -> Result<(), IloaderError> {
+    clear_session(&handle, &email);
+    delete_password(&handle, &email)?;
+    remove_metadata(&handle, &email)?;
+    let mut ids = saved_ids(&handle);
+    ids.retain(|id| id != &email);
+    save_ids(&handle, &ids)
 }
 
 #[tauri::command]
 pub fn logged_in_as(sideloader_state: State<'_, SideloaderMutex>) -> Option<String> {
     let sideloader_guard = sideloader_state.lock().unwrap();
-    if let Some(account) = &*sideloader_guard {
-        return Some(account.get_email().to_string());
-    }
-    None
+    sideloader_guard.active().map(|email| email.to_string())
 }
 
 #[tauri::command]
-pub fn invalidate_account(sideloader_state: State<'_, SideloaderMutex>) {
-    let mut sideloader_guard = sideloader_state.lock().unwrap();
-    *sideloader_guard = None;
+pub fn logged_in_accounts(sideloader_state: State<'_, SideloaderMutex>) -> Vec<String> {
+    let sideloader_guard = sideloader_state.lock().unwrap();
+    sideloader_guard.emails()
 }
 
 #[tauri::command]
-pub fn reset_anisette_state() -> Result<(), String> {
-    let state_entry = Entry::new("iloader", "anisette_state")
-        .map_err(|e| format!("Failed to create keyring entry for anisette: {:?}.", e))?;
-    state_entry
-        .delete_credential()
-        .map_err(|e| format!("Failed to delete anisette state: {:?}", e))?;
+pub fn switch_account(
+    sideloader_state: State<'_, SideloaderMutex>,
+    email: String,
+) -> Result<(), IloaderError> {
+    let mut sideloader_guard = sideloader_state.lock().unwrap();
+    sideloader_guard.set_active(&email.to_lowercase())
+}
 
-    Ok(())
+#[tauri::command]
+pub fn invalidate_account(
+    handle: AppHandle,
+    sideloader_state: State<'_, SideloaderMutex>,
+    email: Option<String>,
+) {
+    let mut sideloader_guard = sideloader_state.lock().unwrap();
+    if let Ok(email) = sideloader_guard.resolve(email.as_deref()) {
+        sideloader_guard.remove(&email);
+        clear_session(&handle, &email);
+    }
 }
 
+// `remember` is whether the user asked to stay signed in; only then is a session token kept
 async fn login(
     app: &AppHandle,
     window: &Window,
     email: &str,
     password: &str,
-    anisette_server: String,
-) -> Result<Sideloader, String> {
-    let window_clone = window.clone();
-    let tfa_closure = move || -> Option<String> {
-        window_clone
-            .emit("2fa-required", ())
-            .expect("Failed to emit 2fa-required event");
-
-        let (tx, rx) = std::sync::mpsc::channel::<String>();
-        let handler_id = window_clone.listen("2fa-recieved", move |event| {
-            let code = event.payload();
-            let _ = tx.send(code.to_string());
-        });
-
-        let result = rx.recv_timeout(Duration::from_secs(120));
-        window_clone.unlisten(handler_id);
-
-        match result {
-            Ok(code) => {
-                let code = code.trim_matches('"').to_string();
-                Some(code)
-            }
-            Err(_) => None,
-        }
-    };
-
-    let anisette_url = if !anisette_server.starts_with("http") {
-        format!("https://{}", anisette_server)
-    } else {
-        anisette_server
-    };
-
-    let (anisette_storage, sideloader_storage): (
-        Box<dyn SideloadingStorage>,
-        Box<dyn SideloadingStorage>,
-    ) = if keyring_available() {
-        (
-            Box::new(KeyringStorage::new("iloader".to_string())),
-            Box::new(KeyringStorage::new("iloader".to_string())),
-        )
-    } else {
-        warn!("Keyring storage is not available, falling back to file storage (less secure)");
-        let data_dir = app
-            .path()
-            .app_data_dir()
-            .unwrap_or_else(|_| PathBuf::from("Failed to get app data directory"));
-        (
-            Box::new(FsStorage::new(data_dir.clone())),
-            Box::new(FsStorage::new(data_dir)),
-        )
-    };
+    anisette_servers: Vec<String>,
+    two_factor_timeout: Option<u64>,
+    remember: bool,
+) -> Result<Sideloader, IloaderError> {
+    if !keyring_available() {
+        warn!("Keyring storage is not available, falling back to encrypted file storage");
+    }
 
-    let mut account = AppleAccount::builder(&email.to_lowercase())
-        .anisette_provider(
-            RemoteV3AnisetteProvider::default()
-                .set_serial_number("0".to_string())
-                .set_storage(anisette_storage)
-                .set_url(&anisette_url),
-        )
-        .login(password, tfa_closure)
-        .await
-        .map_err(|e| e.to_string())?;
+    let (attempt, used_server) =
+        with_failover(servers_for(app, email, anisette_servers)?, |server| {
+            start_login(app, email, password, server)
+        })
+        .await;
+    let (mut account, state) = attempt?;
+    if let Err(e) = update_metadata(app, email, |metadata| {
+        metadata.anisette_server = used_server;
+    }) {
+        warn!("Failed to save anisette server for {}: {}", email, e);
+    }
+
+    let mut exchange = TwoFactorExchange::new(window, two_factor_timeout);
+    complete_two_factor(&mut account, state, &mut exchange).await?;
 
     debug!("Logged in");
 
     let dev_session = DeveloperSession::from_account(&mut account)
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| IloaderError::developer(e.to_string()))?;
 
     debug!("Created developer session");
 
-    let max_certs_callback = {
-        let window_clone = window.clone();
-        move |certs: &Vec<DevelopmentCertificate>| -> Option<Vec<String>> {
-            let cert_infos: Vec<CertificateInfo> = certs
-                .iter()
-                .map(|cert| CertificateInfo {
-                    name: cert.name.clone(),
-                    certificate_id: cert.certificate_id.clone(),
-                    serial_number: cert.serial_number.clone(),
-                    machine_name: cert.machine_name.clone(),
-                    machine_id: cert.machine_id.clone(),
-                })
-                .collect();
-            window_clone
-                .emit("max-certs-reached", cert_infos)
-                .expect("Failed to emit max-certs-reached event");
-
-            let (tx, rx) = std::sync::mpsc::channel::<Option<Vec<String>>>();
-            let handler_id = window_clone.listen("max-certs-response", move |event| {
-                let certs = event.payload();
-                let certs = serde_json::from_str::<Option<Vec<String>>>(certs).unwrap_or(None);
-                let _ = tx.send(certs);
-            });
-
-            let result = rx.recv_timeout(Duration::from_secs(300));
-            window_clone.unlisten(handler_id);
-            result.unwrap_or(None)
-        }
-    };
-
-    // TODO: Team Selection
-
-    let sideloader = SideloaderBuilder::new(dev_session, email.to_lowercase())
-        .machine_name("iloader".to_string())
-        .storage(sideloader_storage)
-        .max_certs_behavior(MaxCertsBehavior::Prompt(Box::new(max_certs_callback)))
-        .build();
-
-    debug!("Built sideloader");
+    if !remember {
+        // don't leave a token from an earlier remembered login behind either
+        clear_session(app, email);
+    } else if let Err(e) = cache_session(app, email, &mut account).await {
+        warn!(
+            "Failed to cache session, the next launch will require a full login: {}",
+            e
+        );
+    }
 
-    Ok(sideloader)
+    build_sideloader(app, window, email, dev_session).await
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct CertificateInfo {
-    pub name: Option<String>,
-    pub certificate_id: Option<String>,
-    pub serial_number: Option<String>,
-    pub machine_name: Option<String>,
-    pub machine_id: Option<String>,
+// Rebuild an account's sideloader from its cached session, so choices that are only read while
+// building it (like the team) take effect. Returns None when a full login is needed instead.
+pub async fn rebuild_sideloader(
+    app: &AppHandle,
+    window: &Window,
+    email: &str,
+) -> Result<Option<Sideloader>, IloaderError> {
+    let server = account_metadata(app, email)
+        .anisette_server
+        .unwrap_or_else(|| DEFAULT_SERVER.to_string());
+    let provider = anisette_provider(app, email, &server)?;
+    match restore_session(app, email, provider).await {
+        Some(dev_session) => Ok(Some(
+            build_sideloader(app, window, email, dev_session).await?,
+        )),
+        None => Ok(None),
+    }
 }
 
-#[tauri::command]
-pub async fn get_certificates(
-    sideloader_state: State<'_, SideloaderMutex>,
-) -> Result<Vec<CertificateInfo>, String> {
-    let mut sideloader = SideloaderGuard::take(&sideloader_state)?;
-
-    let team = sideloader
-        .get_mut()
-        .get_team()
-        .await
-        .map_err(|e| e.to_string())?;
-    let dev_session = sideloader.get_mut().get_dev_session();
+// Sign an account in for background work without making it the active one: from its cached
+// session when possible, otherwise with the saved password. The caller decides where it goes.
+pub async fn login_in_background(
+    app: &AppHandle,
+    window: &Window,
+    email: &str,
+    two_factor_timeout: Option<u64>,
+) -> Result<Sideloader, IloaderError> {
+    if let Some(sideloader) = rebuild_sideloader(app, window, email).await? {
+        debug!("Resumed cached session");
+        return Ok(sideloader);
+    }
 
-    let certificates = dev_session
-        .list_all_development_certs(&team, None)
-        .await
-        .map_err(|e| format!("Failed to get development certificates: {:?}.", e))?;
-
-    Ok(certificates
-        .into_iter()
-        .map(|cert| CertificateInfo {
-            name: cert.name,
-            certificate_id: cert.certificate_id,
-            serial_number: cert.serial_number,
-            machine_name: cert.machine_name,
-            machine_id: cert.machine_id,
-        })
-        .collect())
+    let password = load_password(app, email)?;
+    let server = account_metadata(app, email)
+        .anisette_server
+        .unwrap_or_else(|| DEFAULT_SERVER.to_string());
+    login(
+        app,
+        window,
+        email,
+        &password,
+        server_list(server, None),
+        two_factor_timeout,
+        true,
+    )
+    .await
 }
 
-#[tauri::command]
-pub async fn revoke_certificate(
-    serial_number: String,
-    sideloader_state: State<'_, SideloaderMutex>,
-) -> Result<(), String> {
-    let mut sideloader = SideloaderGuard::take(&sideloader_state)?;
-
-    let team = sideloader
-        .get_mut()
-        .get_team()
-        .await
-        .map_err(|e| e.to_string())?;
-    let dev_session = sideloader.get_mut().get_dev_session();
-
-    dev_session
-        .revoke_development_cert(&team, &serial_number, None)
+async fn start_login(
+    app: &AppHandle,
+    email: &str,
+    password: &str,
+    anisette_server: String,
+) -> Result<(AppleAccount, LoginState), IloaderError> {
+    let mut account = AppleAccount::builder(&email.to_lowercase())
+        .anisette_provider(anisette_provider(app, email, &anisette_server)?)
+        .build()
         .await
-        .map_err(|e| format!("Failed to revoke development certificates: {:?}.", e))?;
+        .map_err(|e| IloaderError::Anisette(e.to_string()))?;
+
+    let state = account.login_with_password(password).await.map_err(|e| {
+        let message = e.to_string();
+        if message.to_lowercase().contains("anisette") {
+            IloaderError::Anisette(message)
+        } else {
+            IloaderError::Auth(message)
+        }
+    })?;
 
-    Ok(())
+    Ok((account, state))
 }
 
-#[tauri::command]
-pub async fn list_app_ids(
-    sideloader_state: State<'_, SideloaderMutex>,
-) -> Result<ListAppIdsResponse, String> {
-    let mut sideloader = SideloaderGuard::take(&sideloader_state)?;
-
-    let team = sideloader
-        .get_mut()
-        .get_team()
-        .await
-        .map_err(|e| e.to_string())?;
-    let dev_session = sideloader.get_mut().get_dev_session();
+async fn build_sideloader(
+    app: &AppHandle,
+    window: &Window,
+    email: &str,
+    dev_session: DeveloperSession,
+) -> Result<Sideloader, IloaderError> {
+    let max_certs_callback = max_certs_callback(app, window, email);
 
-    let response = dev_session
-        .list_app_ids(&team, None)
-        .await
-        .map_err(|e| e.to_string())?;
+    let team_callback = team_selection_callback(app, window, email);
 
-    Ok(response.clone())
-}
+    let mut sideloader = SideloaderBuilder::new(dev_session, email.to_lowercase())
+        .machine_name(MACHINE_NAME.to_string())
+        .storage(storage(app, email)?)
+        .max_certs_behavior(MaxCertsBehavior::Prompt(Box::new(max_certs_callback)))
+        .team_selection(TeamSelection::Prompt(Box::new(team_callback)))
+        .build();
 
-#[tauri::command]
-pub async fn delete_app_id(
-    app_id_id: String,
-    sideloader_state: State<'_, SideloaderMutex>,
-) -> Result<(), String> {
-    let mut sideloader = SideloaderGuard::take(&sideloader_state)?;
+    debug!("Built sideloader");
 
+    // resolve the team now so accounts in multiple teams are asked to pick one while logging in
     let team = sideloader
-        .get_mut()
         .get_team()
         .await
-        .map_err(|e| e.to_string())?;
-    let dev_session = sideloader.get_mut().get_dev_session();
-
-    dev_session
-        .delete_app_id(&team, &app_id_id, None)
-        .await
-        .map_err(|e| format!("Failed to delete App ID: {:?}.", e))?;
-
-    Ok(())
-}
-
-static KEYRING_AVAILABLE: OnceLock<bool> = OnceLock::new();
-
-fn keyring_available() -> bool {
-    *KEYRING_AVAILABLE.get_or_init(check_keyring_available)
-}
-
-fn check_keyring_available() -> bool {
-    let entry = keyring::Entry::new("iloader", "test");
-    if let Ok(entry) = entry {
-        return entry.set_password("test").is_ok() && entry.get_password().is_ok();
+        .map_err(|e| IloaderError::developer(e.to_string()))?;
+    if let Err(e) = record_login(app, email, &team) {
+        warn!("Failed to update account metadata: {}", e);
     }
-    false
+
+    Ok(sideloader)
 }
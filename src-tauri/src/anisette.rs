@@ -0,0 +1,546 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use isideload::anisette::remote_v3::RemoteV3AnisetteProvider;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+use tracing::warn;
+
+use crate::{error::IloaderError, storage::storage};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+// the frontend's default, for logins iloader starts on its own
+pub const DEFAULT_SERVER: &str = "ani.sidestore.io";
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AnisetteServerStatus {
+    pub server: String,
+    pub reachable: bool,
+    pub latency_ms: Option<u64>,
+    /// "v3" for servers that support the provisioning protocol, "v1" for header-only servers
+    pub protocol: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum AnisetteProviderKind {
+    /// The public servers chosen in settings, with failover between them
+    #[default]
+    Public,
+    /// A single self-hosted server; provisioning traffic never goes anywhere else
+    SelfHosted,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AnisetteConfig {
+    pub kind: AnisetteProviderKind,
+    pub server: Option<String>,
+    pub serial_number: Option<String>,
+    /// PEM file with extra root certificates to trust for the self-hosted server
+    pub ca_certificate_path: Option<String>,
+    /// PEM file with the client certificate and private key to present to the server
+    pub client_identity_path: Option<String>,
+}
+
+// per-account provider settings are kept in data.json as a map of account email -> config
+pub fn anisette_config(app: &AppHandle, email: &str) -> AnisetteConfig {
+    app.store("data.json")
+        .ok()
+        .and_then(|store| store.get("anisette"))
+        .and_then(|configs| configs.get(email.to_lowercase()).cloned())
+        .and_then(|config| serde_json::from_value(config).ok())
+        .unwrap_or_default()
+}
+
+// HTTP client honouring a self-hosted server's extra CA and client identity
+fn client_builder(config: &AnisetteConfig) -> Result<reqwest::ClientBuilder, IloaderError> {
+    let mut builder = reqwest::Client::builder();
+    if config.kind != AnisetteProviderKind::SelfHosted {
+        return Ok(builder);
+    }
+    if let Some(path) = &config.ca_certificate_path {
+        let pem = std::fs::read(path)
+            .map_err(|e| IloaderError::Io(format!("Failed to read CA certificate: {}", e)))?;
+        for cert in reqwest::Certificate::from_pem_bundle(&pem)
+            .map_err(|e| IloaderError::Anisette(format!("Invalid CA certificate: {}", e)))?
+        {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    if let Some(path) = &config.client_identity_path {
+        let pem = std::fs::read(path)
+            .map_err(|e| IloaderError::Io(format!("Failed to read client certificate: {}", e)))?;
+        builder =
+            builder.identity(reqwest::Identity::from_pem(&pem).map_err(|e| {
+                IloaderError::Anisette(format!("Invalid client certificate: {}", e))
+            })?);
+    }
+    Ok(builder)
+}
+
+fn http_client(config: &AnisetteConfig) -> Result<reqwest::Client, IloaderError> {
+    client_builder(config)?
+        .build()
+        .map_err(|e| IloaderError::Anisette(format!("Failed to create HTTP client: {}", e)))
+}
+
+fn self_hosted_server(config: &AnisetteConfig) -> Result<String, IloaderError> {
+    config
+        .server
+        .clone()
+        .filter(|server| !server.is_empty())
+        .ok_or_else(|| {
+            IloaderError::Anisette("A self-hosted anisette provider needs a server URL".to_string())
+        })
+}
+
+// Self-hosted accounts only ever talk to their own server, everyone else gets the public list
+pub fn servers_for(
+    app: &AppHandle,
+    email: &str,
+    servers: Vec<String>,
+) -> Result<Vec<String>, IloaderError> {
+    let config = anisette_config(app, email);
+    match config.kind {
+        AnisetteProviderKind::SelfHosted => Ok(vec![self_hosted_server(&config)?]),
+        AnisetteProviderKind::Public => Ok(servers),
+    }
+}
+
+// `anisette_server` is ignored for self-hosted accounts, whose traffic must never leave their
+// own server
+pub fn anisette_provider(
+    app: &AppHandle,
+    email: &str,
+    anisette_server: &str,
+) -> Result<RemoteV3AnisetteProvider, IloaderError> {
+    let config = anisette_config(app, email);
+    let serial_number = config.serial_number.clone().unwrap_or("0".to_string());
+    let server = match config.kind {
+        AnisetteProviderKind::SelfHosted => self_hosted_server(&config)?,
+        AnisetteProviderKind::Public => anisette_server.to_string(),
+    };
+
+    let mut provider = RemoteV3AnisetteProvider::default()
+        .set_serial_number(serial_number)
+        .set_storage(storage(app, email)?)
+        .set_url(&anisette_url(&server));
+
+    if config.kind == AnisetteProviderKind::SelfHosted {
+        provider = provider.set_client(http_client(&config)?);
+    }
+
+    Ok(provider)
+}
+
+#[tauri::command]
+pub fn get_anisette_config(handle: AppHandle, email: String) -> AnisetteConfig {
+    anisette_config(&handle, &email)
+}
+
+#[tauri::command]
+pub fn set_anisette_config(
+    handle: AppHandle,
+    email: String,
+    config: AnisetteConfig,
+) -> Result<(), IloaderError> {
+    if config.kind == AnisetteProviderKind::SelfHosted {
+        self_hosted_server(&config)?;
+        // surface unreadable certificates now rather than on the next login
+        http_client(&config)?;
+    }
+
+    save_anisette_config(&handle, &email, config)
+}
+
+pub fn save_anisette_config(
+    handle: &AppHandle,
+    email: &str,
+    config: AnisetteConfig,
+) -> Result<(), IloaderError> {
+    let store = handle
+        .store("data.json")
+        .map_err(|e| IloaderError::Storage(format!("Failed to get store: {:?}", e)))?;
+    let mut configs = store
+        .get("anisette")
+        .and_then(|v| v.as_object().cloned())
+        .unwrap_or_else(Map::new);
+    configs.insert(
+        email.to_lowercase(),
+        serde_json::to_value(config)
+            .map_err(|e| IloaderError::Storage(format!("Failed to serialize config: {}", e)))?,
+    );
+    store.set("anisette", Value::Object(configs));
+    Ok(())
+}
+
+pub fn anisette_url(server: &str) -> String {
+    if !server.starts_with("http") {
+        format!("https://{}", server)
+    } else {
+        server.to_string()
+    }
+}
+
+// The preferred server first, followed by any fallbacks, without duplicates
+pub fn server_list(primary: String, fallbacks: Option<Vec<String>>) -> Vec<String> {
+    let mut servers = vec![primary];
+    for server in fallbacks.unwrap_or_default() {
+        if !servers.contains(&server) {
+            servers.push(server);
+        }
+    }
+    servers
+}
+
+// Run `attempt` against each server in order, moving on only when the server itself is the
+// problem. Returns the last result along with the server that produced it.
+pub async fn with_failover<T, F, Fut>(
+    servers: Vec<String>,
+    mut attempt: F,
+) -> (Result<T, IloaderError>, Option<String>)
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<T, IloaderError>>,
+{
+    let mut result = Err(IloaderError::Anisette(
+        "No anisette servers configured".to_string(),
+    ));
+    let mut used_server = None;
+    for server in servers {
+        result = attempt(server.clone()).await;
+        used_server = Some(server.clone());
+        match &result {
+            Err(IloaderError::Anisette(e)) => {
+                warn!(
+                    "Anisette server {} failed, trying the next one: {}",
+                    server, e
+                );
+            }
+            _ => break,
+        }
+    }
+    (result, used_server)
+}
+
+async fn json_body(response: reqwest::Response) -> Option<Value> {
+    let text = response.text().await.ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+async fn probe(client: &reqwest::Client, server: String) -> AnisetteServerStatus {
+    let url = anisette_url(&server);
+    let start = Instant::now();
+
+    // v3 servers describe the client they emulate, older servers only hand out headers at /
+    let v3 = client
+        .get(format!("{}/v3/client_info", url.trim_end_matches('/')))
+        .send()
+        .await;
+    let latency_ms = Some(start.elapsed().as_millis() as u64);
+
+    let (protocol, error) = match v3 {
+        Ok(response) if response.status().is_success() => match json_body(response).await {
+            Some(body) if body.get("client_info").is_some() => (Some("v3".to_string()), None),
+            _ => (None, Some("Unexpected response to client_info".to_string())),
+        },
+        Ok(_) => match client.get(&url).send().await {
+            Ok(response) if response.status().is_success() => match json_body(response).await {
+                Some(body) if body.get("X-Apple-I-MD").is_some() => (Some("v1".to_string()), None),
+                _ => (
+                    None,
+                    Some("Server did not return anisette data".to_string()),
+                ),
+            },
+            Ok(response) => (None, Some(format!("HTTP {}", response.status()))),
+            Err(e) => (None, Some(e.to_string())),
+        },
+        Err(e) => {
+            return AnisetteServerStatus {
+                server,
+                reachable: false,
+                latency_ms: None,
+                protocol: None,
+                error: Some(e.to_string()),
+            };
+        }
+    };
+
+    AnisetteServerStatus {
+        server,
+        reachable: true,
+        latency_ms,
+        protocol,
+        error,
+    }
+}
+
+pub async fn probe_servers(
+    config: &AnisetteConfig,
+    servers: Vec<String>,
+) -> Result<Vec<AnisetteServerStatus>, IloaderError> {
+    let client = client_builder(config)?
+        .timeout(PROBE_TIMEOUT)
+        .build()
+        .map_err(|e| IloaderError::Anisette(format!("Failed to create HTTP client: {}", e)))?;
+
+    Ok(futures::future::join_all(servers.into_iter().map(|s| probe(&client, s))).await)
+}
+
+// With an email, servers are probed using that account's CA and client certificate so
+// self-hosted servers can be checked too
+#[tauri::command]
+pub async fn check_anisette_servers(
+    handle: AppHandle,
+    servers: Vec<String>,
+    email: Option<String>,
+) -> Result<Vec<AnisetteServerStatus>, IloaderError> {
+    let config = email
+        .map(|email| anisette_config(&handle, &email))
+        .unwrap_or_default();
+    probe_servers(&config, servers).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    type Route = (&'static str, u16, &'static str);
+
+    // Local stand-in for an anisette server, answering each path with a fixed status and body
+    async fn mock_server(routes: &'static [Route]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 1024];
+                    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match socket.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request);
+                    let path = request.split_whitespace().nth(1).unwrap_or("/");
+                    let (status, body) = routes
+                        .iter()
+                        .find(|(route, _, _)| *route == path)
+                        .map(|(_, status, body)| (*status, *body))
+                        .unwrap_or((404, ""));
+                    let response = format!(
+                        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    async fn check(server: String) -> AnisetteServerStatus {
+        probe_servers(&AnisetteConfig::default(), vec![server])
+            .await
+            .unwrap()
+            .remove(0)
+    }
+
+    #[tokio::test]
+    async fn detects_v3_server() {
+        let server = mock_server(&[(
+            "/v3/client_info",
+            200,
+            r#"{"client_info":"<MacBookPro13,2> <macOS;13.1;22C65> <com.apple.AuthKit/1 (com.apple.dt.Xcode/3594.4.19)>","user_agent":"akd/1.0 CFNetwork/808.1.4"}"#,
+        )])
+        .await;
+
+        let status = check(server.clone()).await;
+        assert_eq!(status.server, server);
+        assert!(status.reachable);
+        assert!(status.latency_ms.is_some());
+        assert_eq!(status.protocol.as_deref(), Some("v3"));
+        assert_eq!(status.error, None);
+    }
+
+    #[tokio::test]
+    async fn detects_v1_header_only_server() {
+        let server = mock_server(&[(
+            "/",
+            200,
+            r#"{"X-Apple-I-MD":"AAAABQAAABD","X-Apple-I-MD-M":"xyz","X-Apple-I-MD-RINFO":"17106176"}"#,
+        )])
+        .await;
+
+        let status = check(server).await;
+        assert!(status.reachable);
+        assert_eq!(status.protocol.as_deref(), Some("v1"));
+        assert_eq!(status.error, None);
+    }
+
+    #[tokio::test]
+    async fn reports_http_errors() {
+        let server = mock_server(&[("/v3/client_info", 500, ""), ("/", 500, "")]).await;
+
+        let status = check(server).await;
+        assert!(status.reachable);
+        assert_eq!(status.protocol, None);
+        assert_eq!(
+            status.error.as_deref(),
+            Some("HTTP 500 Internal Server Error")
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_servers_without_anisette_data() {
+        let server = mock_server(&[("/v3/client_info", 200, "{}"), ("/", 200, "{}")]).await;
+
+        let status = check(server).await;
+        assert!(status.reachable);
+        assert_eq!(status.protocol, None);
+        assert!(status.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn reports_unreachable_servers() {
+        // grab a free port and close it again so nothing is listening there
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let status = check(server).await;
+        assert!(!status.reachable);
+        assert_eq!(status.latency_ms, None);
+        assert_eq!(status.protocol, None);
+        assert!(status.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn keeps_server_order() {
+        let v3 = mock_server(&[("/v3/client_info", 200, r#"{"client_info":"x"}"#)]).await;
+        let v1 = mock_server(&[("/", 200, r#"{"X-Apple-I-MD":"x"}"#)]).await;
+
+        let statuses = probe_servers(&AnisetteConfig::default(), vec![v1.clone(), v3.clone()])
+            .await
+            .unwrap();
+        let servers: Vec<&str> = statuses.iter().map(|s| s.server.as_str()).collect();
+        assert_eq!(servers, vec![v1.as_str(), v3.as_str()]);
+        assert_eq!(statuses[0].protocol.as_deref(), Some("v1"));
+        assert_eq!(statuses[1].protocol.as_deref(), Some("v3"));
+    }
+
+    #[test]
+    fn self_hosted_needs_a_server() {
+        let mut config = AnisetteConfig {
+            kind: AnisetteProviderKind::SelfHosted,
+            ..Default::default()
+        };
+        assert!(matches!(
+            self_hosted_server(&config),
+            Err(IloaderError::Anisette(_))
+        ));
+
+        config.server = Some(String::new());
+        assert!(self_hosted_server(&config).is_err());
+
+        config.server = Some("https://anisette.example.com".to_string());
+        assert_eq!(
+            self_hosted_server(&config).unwrap(),
+            "https://anisette.example.com"
+        );
+    }
+
+    #[test]
+    fn public_accounts_ignore_tls_settings() {
+        let config = AnisetteConfig {
+            ca_certificate_path: Some("/nonexistent/ca.pem".to_string()),
+            ..Default::default()
+        };
+        assert!(http_client(&config).is_ok());
+
+        let config = AnisetteConfig {
+            kind: AnisetteProviderKind::SelfHosted,
+            ..config
+        };
+        assert!(matches!(http_client(&config), Err(IloaderError::Io(_))));
+    }
+
+    fn servers(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn failover_moves_on_after_anisette_errors() {
+        let mut tried = vec![];
+        let (result, used) = with_failover(servers(&["a", "b", "c"]), |server| {
+            tried.push(server.clone());
+            async move {
+                match server.as_str() {
+                    "a" => Err(IloaderError::Anisette("a is down".to_string())),
+                    _ => Ok(server),
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(tried, servers(&["a", "b"]));
+        assert_eq!(result.unwrap(), "b");
+        assert_eq!(used.as_deref(), Some("b"));
+    }
+
+    #[tokio::test]
+    async fn failover_stops_on_other_errors() {
+        for error in [
+            IloaderError::Auth("Incorrect password".to_string()),
+            IloaderError::Network("connection reset".to_string()),
+            IloaderError::TwoFactor("timed out".to_string()),
+        ] {
+            let mut tried = vec![];
+            let (result, used) = with_failover(servers(&["a", "b"]), |server| {
+                tried.push(server);
+                let error = error.clone();
+                async move { Err::<(), _>(error) }
+            })
+            .await;
+
+            assert_eq!(tried, servers(&["a"]));
+            assert_eq!(result.unwrap_err().code(), error.code());
+            assert_eq!(used.as_deref(), Some("a"));
+        }
+    }
+
+    #[tokio::test]
+    async fn failover_returns_the_last_anisette_error() {
+        let mut tried = vec![];
+        let (result, used) = with_failover(servers(&["a", "b"]), |server| {
+            tried.push(server.clone());
+            async move { Err::<(), _>(IloaderError::Anisette(format!("{} is down", server))) }
+        })
+        .await;
+
+        assert_eq!(tried, servers(&["a", "b"]));
+        assert_eq!(result.unwrap_err().message(), "b is down");
+        assert_eq!(used.as_deref(), Some("b"));
+    }
+
+    #[tokio::test]
+    async fn failover_without_servers_is_an_anisette_error() {
+        let (result, used) = with_failover(vec![], |server| async move { Ok(server) }).await;
+        assert!(matches!(result, Err(IloaderError::Anisette(_))));
+        assert_eq!(used, None);
+    }
+}
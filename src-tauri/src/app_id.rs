@@ -0,0 +1,229 @@
+use isideload::dev::{
+    app_ids::{AppId, AppIdsApi, ListAppIdsResponse},
+    teams::DeveloperTeam,
+};
+use serde::Serialize;
+use tauri::{AppHandle, State};
+use tracing::warn;
+
+use crate::{
+    device::{DeviceInfoMutex, installed_bundle_ids},
+    error::IloaderError,
+    sideload::{SideloaderGuard, SideloaderMutex},
+    team::selected_team,
+};
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AppIdInfo {
+    #[serde(flatten)]
+    pub app_id: AppId,
+    /// Whether an app using this App ID is installed on the selected device, None when no
+    /// device is selected or it couldn't be asked
+    pub installed: Option<bool>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AppIdList {
+    pub app_ids: Vec<AppIdInfo>,
+    pub max_quantity: Option<u64>,
+    pub available_quantity: Option<i64>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AppIdDeletionResult {
+    pub app_id_id: String,
+    pub identifier: String,
+    pub deleted: bool,
+    pub skipped_in_use: bool,
+    pub error: Option<IloaderError>,
+}
+
+// App extensions get App IDs of their own below the app's bundle identifier
+fn in_use(identifier: &str, installed: &[String]) -> bool {
+    installed.iter().any(|bundle_id| {
+        identifier == bundle_id
+            || identifier
+                .strip_prefix(bundle_id.as_str())
+                .is_some_and(|rest| rest.starts_with('.'))
+    })
+}
+
+async fn selected_device_bundle_ids(
+    device_state: &State<'_, DeviceInfoMutex>,
+) -> Result<Vec<String>, IloaderError> {
+    let device = device_state
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(IloaderError::DeviceNotSelected)?;
+    installed_bundle_ids(&device).await
+}
+
+// The selected team's App IDs, along with the team so callers don't have to look it up again
+async fn fetch_app_ids(
+    handle: &AppHandle,
+    sideloader: &mut SideloaderGuard<'_>,
+) -> Result<(DeveloperTeam, ListAppIdsResponse), IloaderError> {
+    let team = selected_team(handle, sideloader).await?;
+    let response = sideloader
+        .get_mut()
+        .get_dev_session()
+        .list_app_ids(&team, None)
+        .await
+        .map_err(|e| IloaderError::developer(e.to_string()))?
+        .clone();
+    Ok((team, response))
+}
+
+#[tauri::command]
+pub async fn list_app_ids(
+    handle: AppHandle,
+    sideloader_state: State<'_, SideloaderMutex>,
+    device_state: State<'_, DeviceInfoMutex>,
+    email: Option<String>,
+) -> Result<AppIdList, IloaderError> {
+    let mut sideloader = SideloaderGuard::take(&sideloader_state, email.as_deref())?;
+    let (_, response) = fetch_app_ids(&handle, &mut sideloader).await?;
+
+    let installed = match selected_device_bundle_ids(&device_state).await {
+        Ok(installed) => Some(installed),
+        Err(IloaderError::DeviceNotSelected) => None,
+        Err(e) => {
+            warn!("Failed to check which apps are installed: {}", e);
+            None
+        }
+    };
+
+    let mut app_ids: Vec<AppIdInfo> = response
+        .app_ids
+        .into_iter()
+        .map(|app_id| AppIdInfo {
+            installed: installed
+                .as_ref()
+                .map(|installed| in_use(&app_id.identifier, installed)),
+            app_id,
+        })
+        .collect();
+    // the ones expiring first free up a slot first
+    app_ids.sort_by(|a, b| {
+        a.app_id
+            .expiration_date
+            .is_none()
+            .cmp(&b.app_id.expiration_date.is_none())
+            .then_with(|| a.app_id.expiration_date.cmp(&b.app_id.expiration_date))
+    });
+
+    Ok(AppIdList {
+        app_ids,
+        max_quantity: response.max_quantity,
+        available_quantity: response.available_quantity,
+    })
+}
+
+#[tauri::command]
+pub async fn delete_app_id(
+    handle: AppHandle,
+    app_id_id: String,
+    sideloader_state: State<'_, SideloaderMutex>,
+    email: Option<String>,
+) -> Result<(), IloaderError> {
+    let mut sideloader = SideloaderGuard::take(&sideloader_state, email.as_deref())?;
+
+    let team = selected_team(&handle, &mut sideloader).await?;
+    let dev_session = sideloader.get_mut().get_dev_session();
+
+    dev_session
+        .delete_app_id(&team, &app_id_id, None)
+        .await
+        .map_err(|e| IloaderError::developer(format!("Failed to delete App ID: {:?}.", e)))?;
+
+    Ok(())
+}
+
+// Delete several App IDs, leaving alone any that an app installed on the selected device still
+// uses. A device is required since deleting an App ID in use breaks that app's next refresh.
+#[tauri::command]
+pub async fn delete_app_ids(
+    handle: AppHandle,
+    app_id_ids: Vec<String>,
+    sideloader_state: State<'_, SideloaderMutex>,
+    device_state: State<'_, DeviceInfoMutex>,
+    email: Option<String>,
+) -> Result<Vec<AppIdDeletionResult>, IloaderError> {
+    let installed = selected_device_bundle_ids(&device_state).await?;
+
+    let mut sideloader = SideloaderGuard::take(&sideloader_state, email.as_deref())?;
+    let (team, response) = fetch_app_ids(&handle, &mut sideloader).await?;
+    let dev_session = sideloader.get_mut().get_dev_session();
+
+    let mut results = vec![];
+    for app_id in response
+        .app_ids
+        .into_iter()
+        .filter(|a| app_id_ids.contains(&a.app_id_id))
+    {
+        let mut result = AppIdDeletionResult {
+            app_id_id: app_id.app_id_id.clone(),
+            identifier: app_id.identifier.clone(),
+            deleted: false,
+            skipped_in_use: in_use(&app_id.identifier, &installed),
+            error: None,
+        };
+        if !result.skipped_in_use {
+            match dev_session
+                .delete_app_id(&team, &app_id.app_id_id, None)
+                .await
+            {
+                Ok(_) => result.deleted = true,
+                Err(e) => {
+                    result.error = Some(IloaderError::developer(format!(
+                        "Failed to delete App ID: {:?}.",
+                        e
+                    )))
+                }
+            }
+        }
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn installed() -> Vec<String> {
+        vec![
+            "com.example.app.ABCDE12345".to_string(),
+            "org.other.tool".to_string(),
+        ]
+    }
+
+    #[test]
+    fn the_app_itself_is_in_use() {
+        assert!(in_use("com.example.app.ABCDE12345", &installed()));
+        assert!(in_use("org.other.tool", &installed()));
+    }
+
+    #[test]
+    fn extensions_below_an_installed_app_are_in_use() {
+        assert!(in_use("com.example.app.ABCDE12345.widget", &installed()));
+        assert!(in_use("org.other.tool.share.extension", &installed()));
+    }
+
+    #[test]
+    fn a_shared_prefix_is_not_an_extension() {
+        assert!(!in_use("com.example.app.ABCDE123456", &installed()));
+        assert!(!in_use("org.other.toolbox", &installed()));
+        assert!(!in_use("com.example", &installed()));
+    }
+
+    #[test]
+    fn nothing_is_in_use_without_installed_apps() {
+        assert!(!in_use("com.example.app.ABCDE12345", &[]));
+    }
+}
@@ -0,0 +1,614 @@
+// Optional background refreshing: on a schedule, find apps iloader installed on connected devices
+// that are about to expire and reinstall them from their cached IPAs, signing in with the saved
+// credentials when needed. With the tray enabled iloader keeps running after its window closes.
+use std::{future::Future, sync::Mutex, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tauri::{
+    AppHandle, Emitter, Manager, Window, WindowEvent, Wry,
+    menu::{Menu, MenuItem},
+    tray::TrayIconBuilder,
+};
+use tauri_plugin_store::StoreExt;
+use tracing::{info, warn};
+
+use crate::{
+    account::login_in_background,
+    device::{DeviceInfo, DeviceInfoMutex, list_devices},
+    error::IloaderError,
+    expiry::device_app_expiry,
+    history::{InstallKind, InstallRecord, installed_on},
+    ipa_cache::cached_ipa,
+    sideload::{SideloaderMutex, sideload},
+};
+
+const TRAY_ID: &str = "iloader";
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+const RETRY_BASE_SECS: i64 = 5 * 60;
+const TWO_FACTOR_TIMEOUT_SECS: u64 = 120;
+
+// scheduled and manual runs must not install to the same device at once
+static RUNNING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AutoRefreshConfig {
+    pub enabled: bool,
+    pub run_in_tray: bool,
+    pub interval_hours: u64,
+    /// apps expiring within this many days are refreshed
+    pub threshold_days: i64,
+    /// consecutive failed checks retried sooner than the interval, with backoff
+    pub max_retries: u32,
+}
+
+impl Default for AutoRefreshConfig {
+    fn default() -> Self {
+        AutoRefreshConfig {
+            enabled: false,
+            run_in_tray: false,
+            interval_hours: 6,
+            threshold_days: 2,
+            max_retries: 5,
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshedApp {
+    pub device_name: String,
+    pub bundle_id: String,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoRefreshSummary {
+    pub checked_devices: usize,
+    pub apps: Vec<RefreshedApp>,
+    /// devices whose apps couldn't be checked
+    pub errors: Vec<String>,
+}
+
+impl AutoRefreshSummary {
+    fn failed(&self) -> bool {
+        !self.errors.is_empty() || self.apps.iter().any(|a| a.error.is_some())
+    }
+}
+
+pub fn auto_refresh_config(app: &AppHandle) -> AutoRefreshConfig {
+    app.store("data.json")
+        .ok()
+        .and_then(|store| store.get("autoRefresh"))
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+fn save_auto_refresh_config(
+    app: &AppHandle,
+    config: &AutoRefreshConfig,
+) -> Result<(), IloaderError> {
+    let store = app
+        .store("data.json")
+        .map_err(|e| IloaderError::Storage(format!("Failed to get store: {:?}", e)))?;
+    store.set(
+        "autoRefresh",
+        serde_json::to_value(config)
+            .map_err(|e| IloaderError::Storage(format!("Failed to serialize config: {}", e)))?,
+    );
+    Ok(())
+}
+
+// The tray menu's text, sent by the frontend in its language. Saved so the tray is in that language
+// from the start, before the window has loaded.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TrayLabels {
+    pub show: String,
+    pub refresh: String,
+    pub quit: String,
+}
+
+impl Default for TrayLabels {
+    fn default() -> Self {
+        let en: serde_json::Value = serde_json::from_str(include_str!("../../src/locales/en.json"))
+            .expect("en.json is valid JSON");
+        serde_json::from_value(en["tray"].clone()).expect("en.json has the tray labels")
+    }
+}
+
+fn tray_labels(app: &AppHandle) -> TrayLabels {
+    app.store("data.json")
+        .ok()
+        .and_then(|store| store.get("trayLabels"))
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+// Time source for the scheduler, so its timing can be driven by a fake clock
+pub trait Clock {
+    /// unix timestamp (seconds)
+    fn now(&self) -> i64;
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        chrono::Utc::now().timestamp()
+    }
+
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+        tokio::time::sleep(duration)
+    }
+}
+
+// Decides when checks run. After a failure the next check comes sooner, five minutes then
+// doubling each time, until `max_retries` failures in a row fall back to the normal interval.
+pub struct Scheduler<C: Clock> {
+    clock: C,
+    failures: u32,
+    next_check: i64,
+}
+
+impl<C: Clock> Scheduler<C> {
+    pub fn new(clock: C) -> Self {
+        let next_check = clock.now();
+        Scheduler {
+            clock,
+            failures: 0,
+            next_check,
+        }
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    pub fn due(&self, config: &AutoRefreshConfig) -> bool {
+        config.enabled && self.clock.now() >= self.next_check
+    }
+
+    pub fn finished(&mut self, config: &AutoRefreshConfig, succeeded: bool) {
+        let interval = config.interval_hours.max(1) as i64 * 60 * 60;
+        self.failures = if succeeded { 0 } else { self.failures + 1 };
+
+        let delay = if self.failures == 0 || self.failures > config.max_retries {
+            self.failures = 0;
+            interval
+        } else {
+            (RETRY_BASE_SECS << (self.failures - 1).min(16)).min(interval)
+        };
+        self.next_check = self.clock.now() + delay;
+    }
+}
+
+struct DueApp {
+    installed_bundle_id: String,
+    record: InstallRecord,
+}
+
+// Apps iloader installed on `device` that expire within the threshold. Apps without an installed
+// profile covering them can't be judged and are left alone.
+async fn due_apps(
+    app: &AppHandle,
+    device: &DeviceInfo,
+    threshold_days: i64,
+) -> Result<Vec<DueApp>, IloaderError> {
+    let records = installed_on(app, &device.uuid);
+    if records.is_empty() {
+        return Ok(vec![]);
+    }
+    // records can come from different accounts, so each is matched against its own team below
+    let expiries = device_app_expiry(app, device, None).await?;
+
+    Ok(records
+        .into_iter()
+        .filter_map(|record| {
+            let original = record.bundle_id.as_deref()?;
+            // isideload installs apps as "<bundle id>.<team id>"
+            let suffixed = record
+                .team_id
+                .as_deref()
+                .map(|team_id| format!("{}.{}", original, team_id));
+            let expiry = expiries.iter().find(|e| {
+                e.bundle_id == original || suffixed.as_deref() == Some(e.bundle_id.as_str())
+            })?;
+            if !expiry.refreshable || expiry.days_remaining? > threshold_days {
+                return None;
+            }
+            Some(DueApp {
+                installed_bundle_id: expiry.bundle_id.clone(),
+                record,
+            })
+        })
+        .collect())
+}
+
+async fn ensure_logged_in(app: &AppHandle, email: &str) -> Result<(), IloaderError> {
+    let state = app.state::<SideloaderMutex>();
+    if state
+        .lock()
+        .unwrap()
+        .emails()
+        .contains(&email.to_lowercase())
+    {
+        return Ok(());
+    }
+
+    // prompts for two factor codes or team choices still go to the (possibly hidden) window
+    let window = app
+        .get_webview_window("main")
+        .ok_or_else(|| IloaderError::Other("Main window is gone".to_string()))?
+        .as_ref()
+        .window();
+    let sideloader =
+        login_in_background(app, &window, email, Some(TWO_FACTOR_TIMEOUT_SECS)).await?;
+    // the account the user is working with in the window stays active
+    state
+        .lock()
+        .unwrap()
+        .replace(email.to_lowercase(), sideloader);
+    Ok(())
+}
+
+async fn refresh_due(
+    app: &AppHandle,
+    device: &DeviceInfo,
+    due: &DueApp,
+) -> Result<(), IloaderError> {
+    let record = &due.record;
+    ensure_logged_in(app, &record.email).await?;
+
+    let ipa = cached_ipa(
+        app,
+        &due.installed_bundle_id,
+        record.team_id.as_deref().unwrap_or(""),
+    )
+    .ok_or_else(|| IloaderError::Other(format!("No cached IPA for {}", due.installed_bundle_id)))?;
+    // installs to this device without changing the one selected in the window
+    let device_state: DeviceInfoMutex = Mutex::new(Some(device.clone()));
+    sideload(
+        app,
+        &device_state,
+        &app.state::<SideloaderMutex>(),
+        ipa.to_string_lossy().to_string(),
+        InstallKind::Refresh,
+        None,
+        Some(record.email.clone()),
+    )
+    .await
+    .map(|_| ())
+}
+
+pub async fn refresh_expiring(
+    app: &AppHandle,
+    config: &AutoRefreshConfig,
+) -> Result<AutoRefreshSummary, IloaderError> {
+    let _running = RUNNING.lock().await;
+
+    let devices = list_devices().await?;
+    let mut summary = AutoRefreshSummary {
+        checked_devices: devices.len(),
+        ..Default::default()
+    };
+
+    for device in devices {
+        let due = match due_apps(app, &device, config.threshold_days).await {
+            Ok(due) => due,
+            Err(e) => {
+                warn!("Failed to check apps on {}: {}", device.name, e);
+                summary.errors.push(format!("{}: {}", device.name, e));
+                continue;
+            }
+        };
+
+        for app_due in due {
+            info!(
+                "Refreshing {} on {}",
+                app_due.installed_bundle_id, device.name
+            );
+            let result = refresh_due(app, &device, &app_due).await;
+            if let Err(e) = &result {
+                warn!("Failed to refresh {}: {}", app_due.installed_bundle_id, e);
+            }
+            summary.apps.push(RefreshedApp {
+                device_name: device.name.clone(),
+                bundle_id: app_due.installed_bundle_id,
+                error: result.err().map(|e| e.to_string()),
+            });
+        }
+    }
+
+    Ok(summary)
+}
+
+// Results are also sent to the frontend, since scheduled runs have nobody waiting on them
+async fn run_now(app: &AppHandle) -> Result<AutoRefreshSummary, IloaderError> {
+    let summary = refresh_expiring(app, &auto_refresh_config(app)).await?;
+    let _ = app.emit("auto_refresh", &summary);
+    Ok(summary)
+}
+
+pub async fn run_scheduler(app: AppHandle, clock: impl Clock) {
+    let mut scheduler = Scheduler::new(clock);
+    loop {
+        // checked every minute so config changes apply without a restart
+        scheduler.clock().sleep(POLL_INTERVAL).await;
+        let config = auto_refresh_config(&app);
+        if !scheduler.due(&config) {
+            continue;
+        }
+
+        let succeeded = match run_now(&app).await {
+            Ok(summary) => !summary.failed(),
+            Err(e) => {
+                warn!("Auto-refresh failed: {}", e);
+                false
+            }
+        };
+        scheduler.finished(&config, succeeded);
+    }
+}
+
+fn show_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+fn tray_menu(app: &AppHandle) -> tauri::Result<Menu<Wry>> {
+    let labels = tray_labels(app);
+    let show = MenuItem::with_id(app, "show", &labels.show, true, None::<&str>)?;
+    let refresh = MenuItem::with_id(app, "refresh", &labels.refresh, true, None::<&str>)?;
+    let quit = MenuItem::with_id(app, "quit", &labels.quit, true, None::<&str>)?;
+    Menu::with_items(app, &[&show, &refresh, &quit])
+}
+
+fn build_tray(app: &AppHandle) -> tauri::Result<()> {
+    let menu = tray_menu(app)?;
+
+    let mut tray = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip("iloader")
+        .menu(&menu)
+        .on_menu_event(|app, event| match event.id().as_ref() {
+            "show" => show_main_window(app),
+            "refresh" => {
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move {
+                    if let Err(e) = run_now(&app).await {
+                        warn!("Auto-refresh failed: {}", e);
+                    }
+                });
+            }
+            "quit" => app.exit(0),
+            _ => {}
+        });
+    if let Some(icon) = app.default_window_icon() {
+        tray = tray.icon(icon.clone());
+    }
+    tray.build(app)?;
+    Ok(())
+}
+
+pub fn sync_tray(app: &AppHandle, config: &AutoRefreshConfig) -> tauri::Result<()> {
+    match (config.run_in_tray, app.tray_by_id(TRAY_ID)) {
+        (true, None) => build_tray(app)?,
+        (false, Some(_)) => {
+            app.remove_tray_by_id(TRAY_ID);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// With the tray enabled closing the window only hides it, so scheduled refreshes keep running.
+// Without a tray icon to bring it back, e.g. when creating one failed, the window really closes.
+pub fn hide_on_close(window: &Window, event: &WindowEvent) {
+    if let WindowEvent::CloseRequested { api, .. } = event
+        && auto_refresh_config(window.app_handle()).run_in_tray
+        && window.app_handle().tray_by_id(TRAY_ID).is_some()
+    {
+        api.prevent_close();
+        let _ = window.hide();
+    }
+}
+
+#[tauri::command]
+pub fn get_auto_refresh_config(handle: AppHandle) -> AutoRefreshConfig {
+    auto_refresh_config(&handle)
+}
+
+#[tauri::command]
+pub fn set_auto_refresh_config(
+    handle: AppHandle,
+    config: AutoRefreshConfig,
+) -> Result<(), IloaderError> {
+    save_auto_refresh_config(&handle, &config)?;
+    sync_tray(&handle, &config)
+        .map_err(|e| IloaderError::Other(format!("Failed to update tray icon: {}", e)))
+}
+
+#[tauri::command]
+pub fn set_tray_labels(handle: AppHandle, labels: TrayLabels) -> Result<(), IloaderError> {
+    if tray_labels(&handle) == labels {
+        return Ok(());
+    }
+    let store = handle
+        .store("data.json")
+        .map_err(|e| IloaderError::Storage(format!("Failed to get store: {:?}", e)))?;
+    store.set(
+        "trayLabels",
+        serde_json::to_value(&labels)
+            .map_err(|e| IloaderError::Storage(format!("Failed to serialize labels: {}", e)))?,
+    );
+
+    if let Some(tray) = handle.tray_by_id(TRAY_ID) {
+        tray_menu(&handle)
+            .and_then(|menu| tray.set_menu(Some(menu)))
+            .map_err(|e| IloaderError::Other(format!("Failed to update tray menu: {}", e)))?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn auto_refresh_now(handle: AppHandle) -> Result<AutoRefreshSummary, IloaderError> {
+    run_now(&handle).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    const HOUR: i64 = 60 * 60;
+
+    // Only moves when told to, or when the scheduler sleeps
+    struct FakeClock(AtomicI64);
+
+    impl FakeClock {
+        fn advance(&self, secs: i64) {
+            self.0.fetch_add(secs, Ordering::SeqCst);
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> i64 {
+            self.0.load(Ordering::SeqCst)
+        }
+
+        fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+            self.advance(duration.as_secs() as i64);
+            std::future::ready(())
+        }
+    }
+
+    fn scheduler() -> Scheduler<FakeClock> {
+        Scheduler::new(FakeClock(AtomicI64::new(1_700_000_000)))
+    }
+
+    fn enabled() -> AutoRefreshConfig {
+        AutoRefreshConfig {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    // How long after a finished check the next one becomes due
+    fn delay_after(
+        scheduler: &mut Scheduler<FakeClock>,
+        config: &AutoRefreshConfig,
+        ok: bool,
+    ) -> i64 {
+        scheduler.finished(config, ok);
+        let mut waited = 0;
+        while !scheduler.due(config) {
+            scheduler.clock().advance(60);
+            waited += 60;
+        }
+        waited
+    }
+
+    #[test]
+    fn first_check_is_due_immediately() {
+        assert!(scheduler().due(&enabled()));
+    }
+
+    #[test]
+    fn disabled_is_never_due() {
+        let scheduler = scheduler();
+        scheduler.clock().advance(365 * 24 * HOUR);
+        assert!(!scheduler.due(&AutoRefreshConfig::default()));
+    }
+
+    #[test]
+    fn success_waits_the_interval() {
+        let mut scheduler = scheduler();
+        let config = enabled();
+        scheduler.finished(&config, true);
+        scheduler.clock().advance(6 * HOUR - 1);
+        assert!(!scheduler.due(&config));
+        scheduler.clock().advance(1);
+        assert!(scheduler.due(&config));
+    }
+
+    #[test]
+    fn failures_back_off_then_reset_after_max_retries() {
+        let mut scheduler = scheduler();
+        let config = enabled();
+        let delays: Vec<i64> = (0..7)
+            .map(|_| delay_after(&mut scheduler, &config, false))
+            .collect();
+        assert_eq!(delays, [300, 600, 1200, 2400, 4800, 6 * HOUR, 300]);
+    }
+
+    #[test]
+    fn success_resets_the_backoff() {
+        let mut scheduler = scheduler();
+        let config = enabled();
+        delay_after(&mut scheduler, &config, false);
+        delay_after(&mut scheduler, &config, false);
+        assert_eq!(delay_after(&mut scheduler, &config, true), 6 * HOUR);
+        assert_eq!(delay_after(&mut scheduler, &config, false), 300);
+    }
+
+    #[test]
+    fn backoff_never_exceeds_the_interval() {
+        let mut scheduler = scheduler();
+        let config = AutoRefreshConfig {
+            interval_hours: 1,
+            max_retries: 10,
+            ..enabled()
+        };
+        let delays: Vec<i64> = (0..6)
+            .map(|_| delay_after(&mut scheduler, &config, false))
+            .collect();
+        assert_eq!(delays, [300, 600, 1200, 2400, HOUR, HOUR]);
+    }
+
+    #[test]
+    fn zero_interval_is_treated_as_an_hour() {
+        let mut scheduler = scheduler();
+        let config = AutoRefreshConfig {
+            interval_hours: 0,
+            ..enabled()
+        };
+        assert_eq!(delay_after(&mut scheduler, &config, true), HOUR);
+    }
+
+    #[test]
+    fn sleeping_advances_the_clock() {
+        let scheduler = scheduler();
+        let before = scheduler.clock().now();
+        drop(scheduler.clock().sleep(POLL_INTERVAL));
+        assert_eq!(
+            scheduler.clock().now() - before,
+            POLL_INTERVAL.as_secs() as i64
+        );
+    }
+
+    #[test]
+    fn tray_labels_default_to_english() {
+        let labels = TrayLabels::default();
+        assert_eq!(labels.show, "Show iloader");
+        assert_eq!(labels.refresh, "Refresh Apps Now");
+        assert_eq!(labels.quit, "Quit");
+    }
+
+    #[test]
+    fn every_locale_has_tray_labels() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../src/locales");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let locale: serde_json::Value =
+                serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            assert!(
+                serde_json::from_value::<TrayLabels>(locale["tray"].clone()).is_ok(),
+                "{} is missing tray labels",
+                path.display()
+            );
+        }
+    }
+}
@@ -0,0 +1,885 @@
+use isideload::{
+    dev::{
+        certificates::{CertificatesApi, DevelopmentCertificate},
+        teams::DeveloperTeam,
+    },
+    util::storage::SideloadingStorage,
+};
+use p12_keystore::{Certificate, KeyStore, KeyStoreEntry, PrivateKeyChain};
+use rsa::{RsaPrivateKey, pkcs1::EncodeRsaPublicKey, pkcs8::DecodePrivateKey};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Listener, State, Window};
+use tracing::{info, warn};
+
+use crate::{
+    error::IloaderError,
+    metadata::{account_metadata, update_metadata},
+    operation::Operation,
+    sideload::{SideloaderGuard, SideloaderMutex},
+    storage::{signing_key_key, storage},
+    team::selected_team,
+};
+
+const P12_ALIAS: &str = "iloader";
+// the machine name iloader registers its certificates under
+pub const MACHINE_NAME: &str = "iloader";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateInfo {
+    pub name: Option<String>,
+    pub certificate_id: Option<String>,
+    pub serial_number: Option<String>,
+    pub machine_name: Option<String>,
+    pub machine_id: Option<String>,
+    /// Whether this machine holds the private key for the certificate
+    #[serde(default)]
+    pub local: bool,
+    /// unix timestamps (seconds) from the certificate's validity period
+    pub created_at: Option<i64>,
+    pub expires_at: Option<i64>,
+    pub certificate_type: Option<String>,
+    /// Created by this iloader install, as opposed to another machine or tool
+    #[serde(default)]
+    pub created_by_iloader: bool,
+}
+
+// What this install knows about its own certificate, used to tell it apart from the rest
+#[derive(Default)]
+pub struct LocalCertificate {
+    /// PKCS#1 public key of the account's signing key
+    pub public_key: Option<Vec<u8>>,
+    pub machine_id: Option<String>,
+}
+
+impl LocalCertificate {
+    // The signing key isideload keeps in the account's storage, and the machine ID recorded the
+    // last time the local certificate was seen
+    pub fn load(app: &AppHandle, email: &str) -> Self {
+        let public_key = storage(app, email)
+            .and_then(|storage| load_private_key(storage.as_ref(), email))
+            .and_then(|key| key.as_deref().map(public_key).transpose());
+        LocalCertificate {
+            public_key: public_key
+                .inspect_err(|e| warn!("Failed to load the signing key: {}", e))
+                .ok()
+                .flatten(),
+            machine_id: account_metadata(app, email).machine_id,
+        }
+    }
+
+    // isideload picks its certificate the same way, by the public key
+    fn signs_with(&self, cert: &DevelopmentCertificate) -> bool {
+        self.public_key.is_some()
+            && cert
+                .cert_content
+                .as_ref()
+                .and_then(|content| certificate_public_key(content.as_ref()))
+                == self.public_key
+    }
+
+    // The team's certificate for the signing key. Its machine ID is what the other certificates
+    // this install created share, so it's taken over for telling those apart.
+    fn find<'a>(
+        &mut self,
+        certificates: &'a [DevelopmentCertificate],
+    ) -> Option<&'a DevelopmentCertificate> {
+        let local = certificates.iter().find(|cert| self.signs_with(cert))?;
+        if local.machine_id.is_some() {
+            self.machine_id = local.machine_id.clone();
+        }
+        Some(local)
+    }
+}
+
+struct CertificateDetails {
+    created_at: i64,
+    expires_at: i64,
+    certificate_type: Option<String>,
+}
+
+// Apple puts the certificate type in front of the owner's name in the common name, e.g.
+// "Apple Development: jane@example.com (ABCDE12345)"
+fn certificate_details(der: &[u8]) -> Option<CertificateDetails> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+    let common_name = cert
+        .subject()
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok());
+    Some(CertificateDetails {
+        created_at: cert.validity().not_before.timestamp(),
+        expires_at: cert.validity().not_after.timestamp(),
+        certificate_type: common_name
+            .and_then(|cn| cn.split_once(':'))
+            .map(|(kind, _)| kind.trim().to_string()),
+    })
+}
+
+impl CertificateInfo {
+    pub fn new(cert: &DevelopmentCertificate, local_cert: &LocalCertificate) -> Self {
+        let details = cert
+            .cert_content
+            .as_ref()
+            .and_then(|content| certificate_details(content.as_ref()));
+        let local = local_cert.signs_with(cert);
+        let created_by_iloader = local
+            || (cert.machine_name.as_deref() == Some(MACHINE_NAME)
+                && local_cert.machine_id.is_some()
+                && cert.machine_id == local_cert.machine_id);
+
+        CertificateInfo {
+            name: cert.name.clone(),
+            certificate_id: cert.certificate_id.clone(),
+            serial_number: cert.serial_number.clone(),
+            machine_name: cert.machine_name.clone(),
+            machine_id: cert.machine_id.clone(),
+            local,
+            created_at: details.as_ref().map(|d| d.created_at),
+            expires_at: details.as_ref().map(|d| d.expires_at),
+            certificate_type: details.and_then(|d| d.certificate_type),
+            created_by_iloader,
+        }
+    }
+}
+
+// What to do when the account already has as many certificates as Apple allows
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum MaxCertsPolicy {
+    /// Ask the user which certificates to revoke
+    #[default]
+    Prompt,
+    /// Revoke the oldest certificate that isn't the one this machine signs with
+    RevokeOldest,
+    /// Like RevokeOldest, but only ever touch certificates iloader created
+    RevokeIloaderOnly,
+    /// Give up and report the limit
+    Fail,
+}
+
+// Certificates to revoke under an automatic policy, None when nothing may be revoked
+pub fn certificates_to_revoke(
+    policy: MaxCertsPolicy,
+    certificates: &[CertificateInfo],
+) -> Option<Vec<String>> {
+    let candidates = certificates.iter().filter(|c| !c.local);
+    let oldest = match policy {
+        MaxCertsPolicy::RevokeOldest => candidates.min_by_key(|c| c.created_at.unwrap_or(i64::MAX)),
+        MaxCertsPolicy::RevokeIloaderOnly => candidates
+            .filter(|c| c.created_by_iloader)
+            .min_by_key(|c| c.created_at.unwrap_or(i64::MAX)),
+        MaxCertsPolicy::Prompt | MaxCertsPolicy::Fail => None,
+    }?;
+    Some(vec![oldest.serial_number.clone()?])
+}
+
+// Called by the sideloader when the certificate limit is reached. The account's policy is read
+// each time so changing it takes effect without logging in again.
+pub fn max_certs_callback(
+    app: &AppHandle,
+    window: &Window,
+    email: &str,
+) -> impl Fn(&Vec<DevelopmentCertificate>) -> Option<Vec<String>> + Send + Sync + 'static {
+    let app = app.clone();
+    let window = window.clone();
+    let email = email.to_lowercase();
+    move |certs: &Vec<DevelopmentCertificate>| -> Option<Vec<String>> {
+        let mut local_cert = LocalCertificate::load(&app, &email);
+        local_cert.find(certs);
+        let mut cert_infos: Vec<CertificateInfo> = certs
+            .iter()
+            .map(|cert| CertificateInfo::new(cert, &local_cert))
+            .collect();
+
+        let policy = account_metadata(&app, &email)
+            .max_certs_policy
+            .unwrap_or_default();
+        if policy != MaxCertsPolicy::Prompt {
+            let revoke = certificates_to_revoke(policy, &cert_infos);
+            match &revoke {
+                Some(serials) => info!("Revoking {:?} under the {:?} policy", serials, policy),
+                None => warn!(
+                    "No certificate can be revoked under the {:?} policy",
+                    policy
+                ),
+            }
+            return revoke;
+        }
+
+        sort_by_revocation_cost(&mut cert_infos);
+        window
+            .emit("max-certs-reached", cert_infos)
+            .expect("Failed to emit max-certs-reached event");
+
+        let (tx, rx) = std::sync::mpsc::channel::<Option<Vec<String>>>();
+        let handler_id = window.listen("max-certs-response", move |event| {
+            let certs = event.payload();
+            let certs = serde_json::from_str::<Option<Vec<String>>>(certs).unwrap_or(None);
+            let _ = tx.send(certs);
+        });
+
+        let result = rx.recv_timeout(Duration::from_secs(300));
+        window.unlisten(handler_id);
+        result.unwrap_or(None)
+    }
+}
+
+// Order certificates by how little it costs to revoke them: ones other machines made before our
+// own, and the soonest to expire first
+fn sort_by_revocation_cost(certificates: &mut [CertificateInfo]) {
+    certificates.sort_by_key(|c| {
+        (
+            c.local,
+            c.created_by_iloader,
+            c.expires_at.unwrap_or(i64::MAX),
+        )
+    });
+}
+
+// isideload's signing key for the account, PKCS#8 DER. It is created on the first install and
+// kept for good; the certificate is looked up by its public key whenever it's needed.
+fn load_private_key(
+    storage: &dyn SideloadingStorage,
+    email: &str,
+) -> Result<Option<Vec<u8>>, IloaderError> {
+    storage
+        .retrieve_data(&signing_key_key(email))
+        .map(|key| key.filter(|key| !key.is_empty()))
+        .map_err(|e| IloaderError::Storage(format!("Failed to read the signing key: {}", e)))
+}
+
+fn public_key(private_key: &[u8]) -> Result<Vec<u8>, IloaderError> {
+    let key = RsaPrivateKey::from_pkcs8_der(private_key)
+        .map_err(|e| IloaderError::Other(format!("Invalid private key: {}", e)))?;
+    key.to_public_key()
+        .to_pkcs1_der()
+        .map(|der| der.as_bytes().to_vec())
+        .map_err(|e| IloaderError::Other(format!("Invalid private key: {}", e)))
+}
+
+fn certificate_public_key(der: &[u8]) -> Option<Vec<u8>> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+    Some(cert.public_key().subject_public_key.data.to_vec())
+}
+
+// Serial numbers as uppercase hex without separators or leading zeros, which is how the
+// developer services report them
+fn normalize_serial(serial: &str) -> String {
+    let hex: String = serial
+        .chars()
+        .filter(|c| c.is_ascii_hexdigit())
+        .collect::<String>()
+        .to_uppercase();
+    hex.trim_start_matches('0').to_string()
+}
+
+// Every development certificate of the team, soonest to expire first
+async fn list_certificates(
+    handle: &AppHandle,
+    sideloader: &mut SideloaderGuard<'_>,
+    team: &DeveloperTeam,
+) -> Result<Vec<CertificateInfo>, IloaderError> {
+    let mut local_cert = LocalCertificate::load(handle, sideloader.email());
+    let certificates = team_certificates(sideloader, team).await?;
+
+    if let Some(local) = local_cert.find(&certificates)
+        && let Err(e) = update_metadata(handle, sideloader.email(), |metadata| {
+            metadata.certificate_serial = local.serial_number.clone();
+            metadata.machine_id = local.machine_id.clone();
+        })
+    {
+        warn!("Failed to update account metadata: {}", e);
+    }
+
+    let mut certificates: Vec<CertificateInfo> = certificates
+        .iter()
+        .map(|cert| CertificateInfo::new(cert, &local_cert))
+        .collect();
+    certificates.sort_by_key(|c| c.expires_at.unwrap_or(i64::MAX));
+    Ok(certificates)
+}
+
+#[tauri::command]
+pub async fn get_certificates(
+    handle: AppHandle,
+    sideloader_state: State<'_, SideloaderMutex>,
+    email: Option<String>,
+) -> Result<Vec<CertificateInfo>, IloaderError> {
+    let mut sideloader = SideloaderGuard::take(&sideloader_state, email.as_deref())?;
+
+    let team = selected_team(&handle, &mut sideloader).await?;
+    list_certificates(&handle, &mut sideloader, &team).await
+}
+
+#[tauri::command]
+pub async fn revoke_certificate(
+    handle: AppHandle,
+    serial_number: String,
+    sideloader_state: State<'_, SideloaderMutex>,
+    email: Option<String>,
+) -> Result<(), IloaderError> {
+    let mut sideloader = SideloaderGuard::take(&sideloader_state, email.as_deref())?;
+
+    let team = selected_team(&handle, &mut sideloader).await?;
+    let dev_session = sideloader.get_mut().get_dev_session();
+
+    dev_session
+        .revoke_development_cert(&team, &serial_number, None)
+        .await
+        .map_err(|e| {
+            IloaderError::developer(format!(
+                "Failed to revoke development certificates: {:?}.",
+                e
+            ))
+        })?;
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CertificateFilter {
+    pub machine_name: Option<String>,
+    /// Only certificates created more than this many days ago
+    pub older_than_days: Option<u32>,
+    /// Skip the certificate this machine signs with, on unless turned off explicitly
+    pub exclude_local: bool,
+}
+
+impl Default for CertificateFilter {
+    fn default() -> Self {
+        Self {
+            machine_name: None,
+            older_than_days: None,
+            exclude_local: true,
+        }
+    }
+}
+
+impl CertificateFilter {
+    // Whether the filter narrows anything down by itself, excluding the local certificate doesn't
+    fn has_criteria(&self) -> bool {
+        self.machine_name.is_some() || self.older_than_days.is_some()
+    }
+
+    fn matches(&self, cert: &CertificateInfo, now: i64) -> bool {
+        if self.exclude_local && cert.local {
+            return false;
+        }
+        if let Some(machine_name) = &self.machine_name
+            && cert.machine_name.as_ref() != Some(machine_name)
+        {
+            return false;
+        }
+        if let Some(days) = self.older_than_days {
+            // without a creation date we can't tell, so leave it alone
+            let cutoff = now - i64::from(days) * 24 * 60 * 60;
+            if cert.created_at.is_none_or(|created| created > cutoff) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RevocationResult {
+    pub serial_number: String,
+    pub name: Option<String>,
+    pub error: Option<IloaderError>,
+}
+
+// Revoke many certificates at once, either the given serial numbers or every certificate
+// matching the filter, reporting each one as it goes. Calls that select neither are rejected
+// rather than revoking every certificate of the team.
+#[tauri::command]
+pub async fn revoke_certificates_operation(
+    handle: AppHandle,
+    window: Window,
+    sideloader_state: State<'_, SideloaderMutex>,
+    serial_numbers: Option<Vec<String>>,
+    filter: Option<CertificateFilter>,
+    email: Option<String>,
+) -> Result<Vec<RevocationResult>, IloaderError> {
+    let serial_numbers = serial_numbers
+        .filter(|serials| !serials.is_empty())
+        .map(|serials| {
+            serials
+                .iter()
+                .map(|s| normalize_serial(s))
+                .collect::<Vec<_>>()
+        });
+    let filter = filter.unwrap_or_default();
+    if serial_numbers.is_none() && !filter.has_criteria() {
+        return Err(IloaderError::Other(
+            "Select certificates to revoke or filter them by machine name or age".to_string(),
+        ));
+    }
+
+    let op = Operation::new("revoke_certificates".to_string(), &window);
+    op.start("select")?;
+    let mut sideloader = op.fail_if_err(
+        "select",
+        SideloaderGuard::take(&sideloader_state, email.as_deref()),
+    )?;
+    let team = op.fail_if_err("select", selected_team(&handle, &mut sideloader).await)?;
+    let certificates = op.fail_if_err(
+        "select",
+        list_certificates(&handle, &mut sideloader, &team).await,
+    )?;
+
+    let now = chrono::Utc::now().timestamp();
+    let selected: Vec<CertificateInfo> = certificates
+        .into_iter()
+        .filter(|cert| match &serial_numbers {
+            Some(serials) => cert
+                .serial_number
+                .as_deref()
+                .is_some_and(|s| serials.contains(&normalize_serial(s))),
+            None => true,
+        })
+        .filter(|cert| filter.matches(cert, now))
+        .collect();
+    op.progress(
+        "select",
+        format!("{} certificates selected", selected.len()),
+    )?;
+    op.move_on("select", "revoke")?;
+
+    let dev_session = sideloader.get_mut().get_dev_session();
+    let mut results = vec![];
+    for cert in selected {
+        let Some(serial_number) = cert.serial_number else {
+            continue;
+        };
+        let name = cert.name.as_deref().unwrap_or("Unnamed certificate");
+        let error = match dev_session
+            .revoke_development_cert(&team, &serial_number, None)
+            .await
+        {
+            Ok(_) => {
+                op.progress("revoke", format!("Revoked {} ({})", name, serial_number))?;
+                None
+            }
+            Err(e) => {
+                let error = IloaderError::developer(format!("{:?}", e));
+                op.progress(
+                    "revoke",
+                    format!("Failed to revoke {} ({}): {}", name, serial_number, error),
+                )?;
+                Some(error)
+            }
+        };
+        results.push(RevocationResult {
+            serial_number,
+            name: cert.name,
+            error,
+        });
+    }
+
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    if failed > 0 {
+        return op.fail(
+            "revoke",
+            IloaderError::DeveloperApi(format!(
+                "Failed to revoke {} of {} certificates",
+                failed,
+                results.len()
+            )),
+        );
+    }
+    op.complete("revoke")?;
+    Ok(results)
+}
+
+// The account's signing key and its certificate as a password-protected PKCS#12 file
+fn export_identity(
+    storage: &dyn SideloadingStorage,
+    email: &str,
+    certificates: &[DevelopmentCertificate],
+    password: &str,
+) -> Result<Vec<u8>, IloaderError> {
+    let private_key = load_private_key(storage, email)?.ok_or_else(|| {
+        IloaderError::Other(
+            "There is no signing certificate to export yet, install an app first".to_string(),
+        )
+    })?;
+    let local_cert = LocalCertificate {
+        public_key: Some(public_key(&private_key)?),
+        machine_id: None,
+    };
+    let content = certificates
+        .iter()
+        .find(|cert| local_cert.signs_with(cert))
+        .and_then(|cert| cert.cert_content.as_ref())
+        .ok_or_else(|| {
+            IloaderError::Other(
+                "The signing certificate was revoked, install an app to create a new one"
+                    .to_string(),
+            )
+        })?;
+
+    let certificate = Certificate::from_der(content.as_ref())
+        .map_err(|e| IloaderError::Other(format!("Invalid certificate: {:?}", e)))?;
+    let chain = PrivateKeyChain::new(private_key, [0u8; 20], vec![certificate]);
+    let mut keystore = KeyStore::new();
+    keystore.add_entry(P12_ALIAS, KeyStoreEntry::PrivateKeyChain(chain));
+    keystore
+        .writer(password)
+        .write()
+        .map_err(|e| IloaderError::Other(format!("Failed to create PKCS#12 file: {:?}", e)))
+}
+
+// Make the key in a PKCS#12 file the account's signing key. Its certificate must still be one of
+// the team's and made for iloader, otherwise isideload would ignore it and create a new one.
+fn import_identity(
+    storage: &dyn SideloadingStorage,
+    email: &str,
+    data: &[u8],
+    password: &str,
+    certificates: &[DevelopmentCertificate],
+) -> Result<DevelopmentCertificate, IloaderError> {
+    let keystore = KeyStore::from_pkcs12(data, password).map_err(|e| {
+        IloaderError::Other(format!(
+            "Failed to open PKCS#12 file, check the password: {:?}",
+            e
+        ))
+    })?;
+    let (_, chain) = keystore.private_key_chain().ok_or_else(|| {
+        IloaderError::Other("The PKCS#12 file does not contain a private key".to_string())
+    })?;
+    let local_cert = LocalCertificate {
+        public_key: Some(public_key(chain.key())?),
+        machine_id: None,
+    };
+    if let Some(certificate) = chain.chain().first()
+        && certificate_public_key(certificate.as_der()) != local_cert.public_key
+    {
+        return Err(IloaderError::Other(
+            "The certificate in the PKCS#12 file doesn't belong to its private key".to_string(),
+        ));
+    }
+
+    let cert = certificates
+        .iter()
+        .find(|cert| local_cert.signs_with(cert))
+        .ok_or_else(|| {
+            IloaderError::DeveloperApi(
+                "This certificate was revoked or belongs to a different team".to_string(),
+            )
+        })?;
+    if cert.machine_name.as_deref() != Some(MACHINE_NAME) || cert.machine_id.is_none() {
+        return Err(IloaderError::Other(format!(
+            "This certificate was created by {}, iloader can only sign with its own certificates",
+            cert.machine_name.as_deref().unwrap_or("another tool")
+        )));
+    }
+
+    storage
+        .store_data(&signing_key_key(email), chain.key())
+        .map_err(|e| IloaderError::Storage(format!("Failed to save the signing key: {}", e)))?;
+    Ok(cert.clone())
+}
+
+async fn team_certificates(
+    sideloader: &mut SideloaderGuard<'_>,
+    team: &DeveloperTeam,
+) -> Result<Vec<DevelopmentCertificate>, IloaderError> {
+    sideloader
+        .get_mut()
+        .get_dev_session()
+        .list_all_development_certs(team, None)
+        .await
+        .map_err(|e| {
+            IloaderError::developer(format!("Failed to get development certificates: {:?}.", e))
+        })
+}
+
+// Write the certificate iloader signs with and its private key to a password-protected PKCS#12
+// file, so another machine can import it instead of creating a new certificate.
+#[tauri::command]
+pub async fn export_certificate(
+    handle: AppHandle,
+    sideloader_state: State<'_, SideloaderMutex>,
+    path: String,
+    password: String,
+    email: Option<String>,
+) -> Result<(), IloaderError> {
+    let mut sideloader = SideloaderGuard::take(&sideloader_state, email.as_deref())?;
+    let team = selected_team(&handle, &mut sideloader).await?;
+    let certificates = team_certificates(&mut sideloader, &team).await?;
+
+    let storage = storage(&handle, sideloader.email())?;
+    let data = export_identity(
+        storage.as_ref(),
+        sideloader.email(),
+        &certificates,
+        &password,
+    )?;
+    std::fs::write(&path, data)
+        .map_err(|e| IloaderError::Io(format!("Failed to write {}: {}", path, e)))
+}
+
+// Use the certificate and private key from a PKCS#12 file for signing.
+#[tauri::command]
+pub async fn import_certificate(
+    handle: AppHandle,
+    sideloader_state: State<'_, SideloaderMutex>,
+    path: String,
+    password: String,
+    email: Option<String>,
+) -> Result<CertificateInfo, IloaderError> {
+    let data = std::fs::read(&path)
+        .map_err(|e| IloaderError::Io(format!("Failed to read {}: {}", path, e)))?;
+
+    let mut sideloader = SideloaderGuard::take(&sideloader_state, email.as_deref())?;
+    let team = selected_team(&handle, &mut sideloader).await?;
+    let certificates = team_certificates(&mut sideloader, &team).await?;
+
+    let storage = storage(&handle, sideloader.email())?;
+    let cert = import_identity(
+        storage.as_ref(),
+        sideloader.email(),
+        &data,
+        &password,
+        &certificates,
+    )?;
+    if let Err(e) = update_metadata(&handle, sideloader.email(), |metadata| {
+        metadata.certificate_serial = cert.serial_number.clone();
+        metadata.machine_id = cert.machine_id.clone();
+    }) {
+        warn!("Failed to update account metadata: {}", e);
+    }
+
+    let mut local_cert = LocalCertificate::load(&handle, sideloader.email());
+    local_cert.find(std::slice::from_ref(&cert));
+    Ok(CertificateInfo::new(&cert, &local_cert))
+}
+
+#[tauri::command]
+pub fn get_max_certs_policy(handle: AppHandle, email: String) -> MaxCertsPolicy {
+    account_metadata(&handle, &email)
+        .max_certs_policy
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+pub fn set_max_certs_policy(
+    handle: AppHandle,
+    email: String,
+    policy: MaxCertsPolicy,
+) -> Result<(), IloaderError> {
+    update_metadata(&handle, &email, |metadata| {
+        metadata.max_certs_policy = Some(policy);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use isideload::util::storage::InMemoryStorage;
+
+    const EMAIL: &str = "jane@example.com";
+    const PASSWORD: &str = "hunter2";
+    // an RSA key with a self-signed certificate for it, and a certificate for another key
+    const KEY: &[u8] = include_bytes!("../tests/fixtures/signing-key.der");
+    const CERT: &[u8] = include_bytes!("../tests/fixtures/signing-cert.der");
+    const OTHER_CERT: &[u8] = include_bytes!("../tests/fixtures/other-cert.der");
+
+    fn dev_cert(content: &[u8], serial: &str, machine_name: &str) -> DevelopmentCertificate {
+        DevelopmentCertificate {
+            name: Some("Apple Development: jane@example.com".to_string()),
+            certificate_id: Some(serial.to_string()),
+            serial_number: Some(serial.to_string()),
+            machine_id: Some(format!("machine-{}", serial)),
+            machine_name: Some(machine_name.to_string()),
+            cert_content: Some(content.to_vec().into()),
+            certificate_platform: None,
+            certificate_type: None,
+            status: None,
+            status_code: None,
+            expiration_date: None,
+        }
+    }
+
+    fn team_certs() -> Vec<DevelopmentCertificate> {
+        vec![
+            dev_cert(OTHER_CERT, "E1F2A3B4C5D6E7F", MACHINE_NAME),
+            dev_cert(CERT, "5A1D3C4B7E8F4A0B", MACHINE_NAME),
+        ]
+    }
+
+    fn storage_with_key() -> InMemoryStorage {
+        let storage = InMemoryStorage::new();
+        storage.store_data(&signing_key_key(EMAIL), KEY).unwrap();
+        storage
+    }
+
+    fn local_cert() -> LocalCertificate {
+        LocalCertificate {
+            public_key: Some(public_key(KEY).unwrap()),
+            machine_id: None,
+        }
+    }
+
+    fn info(local: bool, machine_name: &str, created_at: Option<i64>) -> CertificateInfo {
+        CertificateInfo {
+            name: None,
+            certificate_id: None,
+            serial_number: Some("1A".to_string()),
+            machine_name: Some(machine_name.to_string()),
+            machine_id: None,
+            local,
+            created_at,
+            expires_at: None,
+            certificate_type: None,
+            created_by_iloader: false,
+        }
+    }
+
+    #[test]
+    fn export_then_import_restores_the_signing_key() {
+        let p12 = export_identity(&storage_with_key(), EMAIL, &team_certs(), PASSWORD).unwrap();
+
+        let fresh = InMemoryStorage::new();
+        let cert = import_identity(&fresh, EMAIL, &p12, PASSWORD, &team_certs()).unwrap();
+        assert_eq!(cert.serial_number.as_deref(), Some("5A1D3C4B7E8F4A0B"));
+        assert_eq!(
+            fresh.retrieve_data(&signing_key_key(EMAIL)).unwrap(),
+            Some(KEY.to_vec())
+        );
+    }
+
+    #[test]
+    fn import_needs_the_right_password() {
+        let p12 = export_identity(&storage_with_key(), EMAIL, &team_certs(), PASSWORD).unwrap();
+        let fresh = InMemoryStorage::new();
+        assert!(import_identity(&fresh, EMAIL, &p12, "wrong", &team_certs()).is_err());
+        assert_eq!(fresh.retrieve_data(&signing_key_key(EMAIL)).unwrap(), None);
+    }
+
+    #[test]
+    fn import_rejects_revoked_and_foreign_certificates() {
+        let p12 = export_identity(&storage_with_key(), EMAIL, &team_certs(), PASSWORD).unwrap();
+        let fresh = InMemoryStorage::new();
+
+        let revoked = vec![dev_cert(OTHER_CERT, "E1F2A3B4C5D6E7F", MACHINE_NAME)];
+        assert!(import_identity(&fresh, EMAIL, &p12, PASSWORD, &revoked).is_err());
+
+        let foreign = vec![dev_cert(CERT, "5A1D3C4B7E8F4A0B", "SideStore")];
+        assert!(import_identity(&fresh, EMAIL, &p12, PASSWORD, &foreign).is_err());
+        assert_eq!(fresh.retrieve_data(&signing_key_key(EMAIL)).unwrap(), None);
+    }
+
+    #[test]
+    fn export_needs_a_key_and_its_certificate() {
+        assert!(export_identity(&InMemoryStorage::new(), EMAIL, &team_certs(), PASSWORD).is_err());
+
+        let revoked = vec![dev_cert(OTHER_CERT, "E1F2A3B4C5D6E7F", MACHINE_NAME)];
+        assert!(export_identity(&storage_with_key(), EMAIL, &revoked, PASSWORD).is_err());
+    }
+
+    #[test]
+    fn local_certificate_is_found_by_public_key() {
+        let certs = team_certs();
+        let mut local_cert = local_cert();
+        let found = local_cert.find(&certs).unwrap();
+        assert_eq!(found.serial_number.as_deref(), Some("5A1D3C4B7E8F4A0B"));
+        assert_eq!(
+            local_cert.machine_id.as_deref(),
+            Some("machine-5A1D3C4B7E8F4A0B")
+        );
+
+        let infos: Vec<CertificateInfo> = certs
+            .iter()
+            .map(|cert| CertificateInfo::new(cert, &local_cert))
+            .collect();
+        assert!(!infos[0].local);
+        assert!(infos[1].local && infos[1].created_by_iloader);
+    }
+
+    #[test]
+    fn no_signing_key_means_no_local_certificate() {
+        let certs = team_certs();
+        let mut local_cert = LocalCertificate::default();
+        assert!(local_cert.find(&certs).is_none());
+        assert!(
+            certs
+                .iter()
+                .all(|cert| !CertificateInfo::new(cert, &local_cert).local)
+        );
+    }
+
+    #[test]
+    fn certificates_sharing_the_machine_id_are_iloaders() {
+        let mut sibling = dev_cert(OTHER_CERT, "E1F2A3B4C5D6E7F", MACHINE_NAME);
+        sibling.machine_id = Some("machine-5A1D3C4B7E8F4A0B".to_string());
+        let certs = vec![sibling, dev_cert(CERT, "5A1D3C4B7E8F4A0B", MACHINE_NAME)];
+        let mut local_cert = local_cert();
+        local_cert.find(&certs);
+
+        let sibling = CertificateInfo::new(&certs[0], &local_cert);
+        assert!(!sibling.local && sibling.created_by_iloader);
+    }
+
+    #[test]
+    fn default_filter_keeps_the_local_certificate() {
+        let filter = CertificateFilter::default();
+        assert!(!filter.matches(&info(true, MACHINE_NAME, Some(0)), 0));
+        assert!(filter.matches(&info(false, MACHINE_NAME, Some(0)), 0));
+        assert!(!filter.has_criteria());
+    }
+
+    #[test]
+    fn filter_can_include_the_local_certificate() {
+        let filter = CertificateFilter {
+            exclude_local: false,
+            ..Default::default()
+        };
+        assert!(filter.matches(&info(true, MACHINE_NAME, Some(0)), 0));
+    }
+
+    #[test]
+    fn filter_by_machine_name() {
+        let filter = CertificateFilter {
+            machine_name: Some("SideStore".to_string()),
+            ..Default::default()
+        };
+        assert!(filter.has_criteria());
+        assert!(filter.matches(&info(false, "SideStore", None), 0));
+        assert!(!filter.matches(&info(false, MACHINE_NAME, None), 0));
+    }
+
+    #[test]
+    fn filter_by_age() {
+        let day = 24 * 60 * 60;
+        let now = 100 * day;
+        let filter = CertificateFilter {
+            older_than_days: Some(30),
+            ..Default::default()
+        };
+        assert!(filter.matches(&info(false, MACHINE_NAME, Some(now - 31 * day)), now));
+        assert!(!filter.matches(&info(false, MACHINE_NAME, Some(now - 29 * day)), now));
+        // without a creation date the age is unknown
+        assert!(!filter.matches(&info(false, MACHINE_NAME, None), now));
+    }
+
+    #[test]
+    fn filter_from_the_frontend_excludes_local_unless_told() {
+        let filter: CertificateFilter = serde_json::from_str(r#"{"olderThanDays": 7}"#).unwrap();
+        assert!(filter.exclude_local);
+        let filter: CertificateFilter = serde_json::from_str(r#"{"excludeLocal": false}"#).unwrap();
+        assert!(!filter.exclude_local);
+    }
+
+    #[test]
+    fn serials_are_normalized() {
+        assert_eq!(normalize_serial("5A1D3C4B7E8F4A0B"), "5A1D3C4B7E8F4A0B");
+        assert_eq!(normalize_serial("5a:1d:3c:4b"), "5A1D3C4B");
+        assert_eq!(normalize_serial("00 0E 1F"), "E1F");
+        assert_eq!(normalize_serial(""), "");
+    }
+}
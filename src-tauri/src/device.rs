@@ -1,14 +1,17 @@
-use std::sync::Mutex;
+use std::{collections::HashMap, sync::Mutex};
 
 use idevice::{
+    IdeviceService,
+    installation_proxy::InstallationProxyClient,
     lockdown::LockdownClient,
     provider::UsbmuxdProvider,
     usbmuxd::{Connection, UsbmuxdAddr, UsbmuxdConnection},
-    IdeviceService,
 };
 use serde::{Deserialize, Serialize};
 use tauri::State;
 
+use crate::error::IloaderError;
+
 #[derive(Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct DeviceInfo {
@@ -21,15 +24,20 @@ pub struct DeviceInfo {
 pub type DeviceInfoMutex = Mutex<Option<DeviceInfo>>;
 
 #[tauri::command]
-pub async fn list_devices() -> Result<Vec<DeviceInfo>, String> {
+pub async fn list_devices() -> Result<Vec<DeviceInfo>, IloaderError> {
     let usbmuxd = UsbmuxdConnection::default().await;
     if usbmuxd.is_err() {
         eprintln!("Failed to connect to usbmuxd: {:?}", usbmuxd.err());
-        return Err("Failed to connect to usbmuxd".to_string());
+        return Err(IloaderError::Usbmuxd(
+            "Failed to connect to usbmuxd".to_string(),
+        ));
     }
     let mut usbmuxd = usbmuxd.unwrap();
 
-    let devs = usbmuxd.get_devices().await.unwrap();
+    let devs = usbmuxd
+        .get_devices()
+        .await
+        .map_err(|e| IloaderError::Usbmuxd(format!("Failed to list devices: {}", e)))?;
     if devs.is_empty() {
         return Ok(vec![]);
     }
@@ -83,16 +91,16 @@ pub async fn list_devices() -> Result<Vec<DeviceInfo>, String> {
 pub async fn set_selected_device(
     device_state: State<'_, DeviceInfoMutex>,
     device: Option<DeviceInfo>,
-) -> Result<(), String> {
+) -> Result<(), IloaderError> {
     let mut device_state = device_state.lock().unwrap();
     *device_state = device;
     Ok(())
 }
 
-pub async fn get_provider(device_info: &DeviceInfo) -> Result<UsbmuxdProvider, String> {
+pub async fn get_provider(device_info: &DeviceInfo) -> Result<UsbmuxdProvider, IloaderError> {
     let mut usbmuxd = UsbmuxdConnection::default()
         .await
-        .map_err(|e| format!("Failed to connect to usbmuxd: {}", e))?;
+        .map_err(|e| IloaderError::Usbmuxd(format!("Failed to connect to usbmuxd: {}", e)))?;
 
     get_provider_from_connection(device_info, &mut usbmuxd).await
 }
@@ -100,12 +108,39 @@ pub async fn get_provider(device_info: &DeviceInfo) -> Result<UsbmuxdProvider, S
 pub async fn get_provider_from_connection(
     device_info: &DeviceInfo,
     connection: &mut UsbmuxdConnection,
-) -> Result<UsbmuxdProvider, String> {
+) -> Result<UsbmuxdProvider, IloaderError> {
     let device = connection
         .get_device(&device_info.uuid)
         .await
-        .map_err(|e| format!("Failed to get device: {}", e))?;
+        .map_err(|e| IloaderError::DeviceDisconnected(format!("Failed to get device: {}", e)))?;
 
     let provider = device.to_provider(UsbmuxdAddr::from_env_var().unwrap(), "iloader");
     Ok(provider)
 }
+
+// User apps on the device keyed by bundle ID, with the attributes installation_proxy reports
+pub async fn installed_apps(
+    device: &DeviceInfo,
+) -> Result<HashMap<String, plist::Value>, IloaderError> {
+    let provider = get_provider(device).await?;
+    let mut installation_proxy =
+        InstallationProxyClient::connect(&provider)
+            .await
+            .map_err(|e| {
+                IloaderError::InstallationProxy(format!(
+                    "Failed to connect to installation proxy: {}",
+                    e
+                ))
+            })?;
+
+    installation_proxy
+        .get_apps(Some("User"), None)
+        .await
+        .map_err(|e| {
+            IloaderError::InstallationProxy(format!("Failed to get installed apps: {}", e))
+        })
+}
+
+pub async fn installed_bundle_ids(device: &DeviceInfo) -> Result<Vec<String>, IloaderError> {
+    Ok(installed_apps(device).await?.into_keys().collect())
+}
@@ -0,0 +1,572 @@
+// Known failure messages from isideload, idevice and Apple's developer services, mapped to what
+// usually causes them and how to fix it. Patterns are matched case-insensitively against the
+// raw error text, first match wins, so more specific entries go first. This is also the table
+// IloaderError uses to classify untyped errors and pick their hint.
+use serde::Serialize;
+
+use crate::error::IloaderError;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnosis {
+    pub cause: &'static str,
+    pub steps: &'static [&'static str],
+    /// Key the frontend and docs use to link to a longer explanation
+    pub doc_key: &'static str,
+    /// The kind of error a message matching this entry is
+    #[serde(skip)]
+    pub error: fn(String) -> IloaderError,
+}
+
+const DIAGNOSES: &[(&[&str], Diagnosis)] = &[
+    (
+        &[
+            "maximum number of apps",
+            "maximumnumberofapps",
+            "0xe8008021",
+        ],
+        Diagnosis {
+            cause: "Free Apple accounts can only have 3 sideloaded apps installed at once.",
+            steps: &[
+                "Delete a sideloaded app you no longer need from your device.",
+                "Try installing again.",
+            ],
+            doc_key: "max-apps",
+            error: IloaderError::QuotaExceeded,
+        },
+    ),
+    (
+        &[
+            "maximum app id",
+            "app id limit",
+            "maximum number of app ids",
+            "10 app ids",
+        ],
+        Diagnosis {
+            cause: "Free Apple accounts can only register 10 App IDs every 7 days.",
+            steps: &[
+                "Remove App IDs you no longer need on the App IDs page.",
+                "Otherwise, wait until the oldest App ID expires.",
+            ],
+            doc_key: "max-app-ids",
+            error: IloaderError::QuotaExceeded,
+        },
+    ),
+    (
+        &[
+            "maximum number of certificates",
+            "too many certificates",
+            "certificate limit",
+            "already have a current ios development certificate",
+        ],
+        Diagnosis {
+            cause: "Your account has reached the maximum number of development certificates.",
+            steps: &[
+                "Revoke an unused certificate on the Certificates page.",
+                "Try again; iloader will create a new certificate.",
+            ],
+            doc_key: "max-certs",
+            error: IloaderError::QuotaExceeded,
+        },
+    ),
+    (
+        &["anisette"],
+        Diagnosis {
+            cause: "The anisette server could not provide the data Apple needs to sign you in.",
+            steps: &[
+                "Choose a different anisette server in settings.",
+                "If that doesn't help, reset the anisette state in settings and sign in again.",
+            ],
+            doc_key: "anisette",
+            error: IloaderError::Anisette,
+        },
+    ),
+    (
+        &[
+            "session has expired",
+            "session expired",
+            "developer error 1100",
+        ],
+        Diagnosis {
+            cause: "Your Apple developer session is no longer valid.",
+            steps: &["Sign out of your Apple ID in iloader.", "Sign in again."],
+            doc_key: "session-expired",
+            error: IloaderError::Auth,
+        },
+    ),
+    (
+        &["passwordprotected", "device is locked", "devicelocked"],
+        Diagnosis {
+            cause: "The device is locked.",
+            steps: &["Unlock your device and keep it unlocked.", "Try again."],
+            doc_key: "device-locked",
+            error: IloaderError::Lockdown,
+        },
+    ),
+    (
+        &[
+            "invalidhostid",
+            "pairing record",
+            "pairingdialogresponsepending",
+            "usermustrepair",
+        ],
+        Diagnosis {
+            cause: "This computer is not trusted by the device, or the pairing is stale.",
+            steps: &[
+                "Unplug and reconnect the device.",
+                "Unlock it and tap \"Trust\" when asked.",
+                "If it keeps failing, reset Location & Privacy on the device and pair again.",
+            ],
+            doc_key: "pairing",
+            error: IloaderError::Pairing,
+        },
+    ),
+    (
+        &["usbmuxd"],
+        Diagnosis {
+            cause: "iloader could not talk to the USB multiplexing service.",
+            steps: &[
+                "On Windows, install iTunes or Apple Devices from Apple.",
+                "On Linux, install and start usbmuxd.",
+                "Reconnect the device and refresh the device list.",
+            ],
+            doc_key: "usbmuxd",
+            error: IloaderError::Usbmuxd,
+        },
+    ),
+    (
+        &[
+            "applicationverificationfailed",
+            "valid provisioning profile",
+        ],
+        Diagnosis {
+            cause: "The device rejected the app's signature or provisioning profile.",
+            steps: &[
+                "Make sure the device's date and time are correct.",
+                "Revoke iloader's certificate on the Certificates page and install again.",
+            ],
+            doc_key: "verification-failed",
+            error: IloaderError::InstallationProxy,
+        },
+    ),
+    (
+        &["developer mode", "developermode"],
+        Diagnosis {
+            cause: "Developer Mode is disabled on the device.",
+            steps: &[
+                "Open Settings > Privacy & Security > Developer Mode on the device.",
+                "Turn it on and restart the device when asked.",
+            ],
+            doc_key: "developer-mode",
+            error: IloaderError::InstallationProxy,
+        },
+    ),
+    (
+        &["two-factor authentication timed out"],
+        Diagnosis {
+            cause: "The verification code wasn't entered in time.",
+            steps: &[
+                "Sign in again and keep an eye out for the verification prompt.",
+                "If the code never arrives, request it by SMS instead.",
+            ],
+            doc_key: "two-factor",
+            error: IloaderError::TwoFactor,
+        },
+    ),
+    (
+        &["afc"],
+        Diagnosis {
+            cause: "Copying files to or from the device failed.",
+            steps: &["Keep the device unlocked and connected.", "Try again."],
+            doc_key: "afc",
+            error: IloaderError::AfcIo,
+        },
+    ),
+    (
+        &["lockdown"],
+        Diagnosis {
+            cause: "iloader could not talk to the device.",
+            steps: &[
+                "Unlock your device and tap \"Trust\" when asked.",
+                "Reconnect the device and try again.",
+            ],
+            doc_key: "lockdown",
+            error: IloaderError::Lockdown,
+        },
+    ),
+    (
+        &[
+            "operation timed out",
+            "connection timed out",
+            "connection refused",
+            "dns error",
+            "error sending request",
+        ],
+        Diagnosis {
+            cause: "A network request failed.",
+            steps: &[
+                "Check your internet connection.",
+                "Try again in a few minutes.",
+            ],
+            doc_key: "network",
+            error: IloaderError::Network,
+        },
+    ),
+];
+
+pub fn diagnose(message: &str) -> Option<&'static Diagnosis> {
+    let message = message.to_lowercase();
+    DIAGNOSES
+        .iter()
+        .find(|(patterns, _)| patterns.iter().any(|p| message.contains(p)))
+        .map(|(_, diagnosis)| diagnosis)
+}
+
+#[tauri::command]
+pub fn diagnose_error(message: String) -> Option<&'static Diagnosis> {
+    diagnose(&message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc_key(message: &str) -> Option<&'static str> {
+        diagnose(message).map(|d| d.doc_key)
+    }
+
+    #[test]
+    fn max_apps() {
+        assert_eq!(
+            doc_key(
+                "Failed to install app: 0xe8008021 (The maximum number of apps for free development profiles has been reached.)"
+            ),
+            Some("max-apps")
+        );
+    }
+
+    #[test]
+    fn max_app_ids() {
+        assert_eq!(
+            doc_key(
+                "Your maximum App ID limit has been reached. You may create up to 10 App IDs every 7 days."
+            ),
+            Some("max-app-ids")
+        );
+    }
+
+    #[test]
+    fn max_certs() {
+        assert_eq!(
+            doc_key(
+                "You already have a current iOS Development certificate or a pending certificate request."
+            ),
+            Some("max-certs")
+        );
+    }
+
+    #[test]
+    fn session_expired() {
+        assert_eq!(
+            doc_key("Your session has expired. Please log in."),
+            Some("session-expired")
+        );
+    }
+
+    #[test]
+    fn anisette() {
+        assert_eq!(
+            doc_key("Failed to provision anisette: invalid response from server"),
+            Some("anisette")
+        );
+    }
+
+    #[test]
+    fn device_locked() {
+        assert_eq!(
+            doc_key("Failed to start lockdown session: PasswordProtected"),
+            Some("device-locked")
+        );
+    }
+
+    #[test]
+    fn pairing() {
+        assert_eq!(
+            doc_key("Failed to start lockdown session: InvalidHostID"),
+            Some("pairing")
+        );
+    }
+
+    #[test]
+    fn usbmuxd() {
+        assert_eq!(
+            doc_key("Failed to connect to usbmuxd: No such file or directory (os error 2)"),
+            Some("usbmuxd")
+        );
+    }
+
+    #[test]
+    fn verification_failed() {
+        assert_eq!(
+            doc_key(
+                "ApplicationVerificationFailed: Failed to verify code signature of App.app : 0xe8008015 (A valid provisioning profile for this executable was not found.)"
+            ),
+            Some("verification-failed")
+        );
+    }
+
+    #[test]
+    fn developer_mode() {
+        assert_eq!(
+            doc_key("Installation failed: DeveloperModeNotEnabled"),
+            Some("developer-mode")
+        );
+    }
+
+    #[test]
+    fn two_factor() {
+        assert_eq!(
+            doc_key("Two-factor authentication timed out after 300 seconds without a code"),
+            Some("two-factor")
+        );
+    }
+
+    #[test]
+    fn afc() {
+        assert_eq!(
+            doc_key("Failed to write file over AFC: ObjectNotFound"),
+            Some("afc")
+        );
+    }
+
+    #[test]
+    fn lockdown() {
+        assert_eq!(
+            doc_key("Failed to connect to lockdown: broken pipe"),
+            Some("lockdown")
+        );
+    }
+
+    #[test]
+    fn network() {
+        assert_eq!(
+            doc_key(
+                "error sending request for url (https://developerservices2.apple.com/services/QH65B2/listTeams.action): operation timed out"
+            ),
+            Some("network")
+        );
+    }
+
+    #[test]
+    fn unknown_messages_have_no_diagnosis() {
+        assert_eq!(doc_key("Something unexpected happened"), None);
+    }
+
+    // One real-world message per pattern in DIAGNOSES
+    const FIXTURES: &[(&str, &str)] = &[
+        (
+            "maximum number of apps",
+            "The maximum number of apps for free development profiles has been reached.",
+        ),
+        (
+            "maximumnumberofapps",
+            "Installation failed: MaximumNumberOfAppsReached",
+        ),
+        ("0xe8008021", "Failed to install app: 0xe8008021"),
+        (
+            "maximum app id",
+            "Developer error 9401: You have reached the maximum App ID count for this team.",
+        ),
+        (
+            "app id limit",
+            "Developer error 9401: App ID limit reached for this account.",
+        ),
+        (
+            "maximum number of app ids",
+            "You have reached the maximum number of App IDs you can create this week.",
+        ),
+        (
+            "10 app ids",
+            "Free accounts can create up to 10 App IDs every 7 days.",
+        ),
+        (
+            "maximum number of certificates",
+            "Developer error 7460: You have reached the maximum number of certificates.",
+        ),
+        (
+            "too many certificates",
+            "Too many certificates have been created for this team.",
+        ),
+        (
+            "certificate limit",
+            "Failed to create certificate: certificate limit reached",
+        ),
+        (
+            "already have a current ios development certificate",
+            "You already have a current iOS Development certificate or a pending certificate request.",
+        ),
+        (
+            "anisette",
+            "Failed to get anisette headers: HTTP status client error (401 Unauthorized) for url (https://ani.sidestore.io/v3/get_headers)",
+        ),
+        (
+            "session has expired",
+            "Your session has expired. Please log in.",
+        ),
+        ("session expired", "Failed to list teams: session expired"),
+        ("developer error 1100", "Developer error 1100: Unauthorized"),
+        (
+            "passwordprotected",
+            "Failed to start lockdown session: PasswordProtected",
+        ),
+        (
+            "device is locked",
+            "Could not mount the developer image, the device is locked",
+        ),
+        ("devicelocked", "Failed to start service: DeviceLocked"),
+        (
+            "invalidhostid",
+            "Failed to start lockdown session: InvalidHostID",
+        ),
+        (
+            "pairing record",
+            "Failed to read pairing record: No such file or directory (os error 2)",
+        ),
+        (
+            "pairingdialogresponsepending",
+            "Failed to pair: PairingDialogResponsePending",
+        ),
+        ("usermustrepair", "Failed to pair: UserMustRepair"),
+        (
+            "usbmuxd",
+            "Failed to connect to usbmuxd: No such file or directory (os error 2)",
+        ),
+        (
+            "applicationverificationfailed",
+            "ApplicationVerificationFailed: Failed to verify code signature of App.app : 0xe8008015",
+        ),
+        (
+            "valid provisioning profile",
+            "0xe8008015 (A valid provisioning profile for this executable was not found.)",
+        ),
+        (
+            "developer mode",
+            "Developer Mode must be enabled to install this app",
+        ),
+        (
+            "developermode",
+            "Installation failed: DeveloperModeNotEnabled",
+        ),
+        (
+            "two-factor authentication timed out",
+            "Two-factor authentication timed out after 300 seconds without a code",
+        ),
+        ("afc", "Failed to write file over AFC: ObjectNotFound"),
+        ("lockdown", "Failed to connect to lockdown: broken pipe"),
+        (
+            "operation timed out",
+            "Failed to list devices: operation timed out",
+        ),
+        (
+            "connection timed out",
+            "Failed to connect: Connection timed out (os error 110)",
+        ),
+        (
+            "connection refused",
+            "Failed to connect: Connection refused (os error 111)",
+        ),
+        (
+            "dns error",
+            "client error (Connect): dns error: failed to lookup address information",
+        ),
+        (
+            "error sending request",
+            "error sending request for url (https://developerservices2.apple.com/services/QH65B2/listTeams.action)",
+        ),
+    ];
+
+    #[test]
+    fn every_pattern_is_reachable() {
+        // a pattern shadowed by an earlier entry's pattern would never pick its own entry
+        for (patterns, diagnosis) in DIAGNOSES {
+            for pattern in *patterns {
+                let (_, message) = FIXTURES
+                    .iter()
+                    .find(|(p, _)| p == pattern)
+                    .unwrap_or_else(|| panic!("no fixture for \"{}\"", pattern));
+                assert!(
+                    message.to_lowercase().contains(pattern),
+                    "fixture for \"{}\" doesn't contain it",
+                    pattern
+                );
+                assert_eq!(
+                    doc_key(message),
+                    Some(diagnosis.doc_key),
+                    "\"{}\" is shadowed by an earlier entry",
+                    pattern
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_fixture_is_a_pattern() {
+        for (pattern, _) in FIXTURES {
+            assert!(
+                DIAGNOSES
+                    .iter()
+                    .any(|(patterns, _)| patterns.contains(pattern)),
+                "\"{}\" is not in the table",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn anisette_unauthorized_is_not_an_expired_session() {
+        assert_eq!(
+            doc_key(
+                "Failed to get anisette headers: HTTP status client error (401 Unauthorized) for url (https://ani.sidestore.io/v3/get_headers)"
+            ),
+            Some("anisette")
+        );
+    }
+
+    #[test]
+    fn mentioning_three_apps_is_not_the_app_limit() {
+        assert_eq!(doc_key("Refreshed 3 apps on iPhone"), None);
+    }
+
+    #[test]
+    fn first_match_wins() {
+        // the app limit is reported as a verification failure
+        assert_eq!(
+            doc_key(
+                "ApplicationVerificationFailed: 0xe8008021 (The maximum number of apps for free development profiles has been reached.)"
+            ),
+            Some("max-apps")
+        );
+        // anisette failures are usually network errors underneath
+        assert_eq!(
+            doc_key(
+                "Failed to fetch anisette data: error sending request for url (https://ani.sidestore.io/v3/client_info)"
+            ),
+            Some("anisette")
+        );
+        // a locked device also fails the lockdown session
+        assert_eq!(
+            doc_key("lockdown: PasswordProtected"),
+            Some("device-locked")
+        );
+        // the two-factor timeout isn't a network timeout
+        assert_eq!(
+            doc_key("Two-factor authentication timed out, operation timed out"),
+            Some("two-factor")
+        );
+    }
+
+    #[test]
+    fn matching_ignores_case() {
+        assert_eq!(doc_key("USBMUXD IS NOT RUNNING"), Some("usbmuxd"));
+    }
+}
@@ -0,0 +1,299 @@
+// File-backed SideloadingStorage that encrypts every value at rest, for systems without a usable
+// OS keyring. The key is derived from ILOADER_STORAGE_PASSPHRASE when it is set, otherwise from
+// this machine's ID, so copying the files to another machine doesn't expose their contents.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use aes_gcm::{
+    Aes256Gcm, Key, Nonce,
+    aead::{Aead, KeyInit},
+};
+use isideload::util::storage::SideloadingStorage;
+use rand::RngCore;
+use rootcause::{Report, report};
+use sha2::Sha256;
+
+use crate::error::IloaderError;
+
+pub const PASSPHRASE_ENV: &str = "ILOADER_STORAGE_PASSPHRASE";
+const SALT_FILE: &str = "storage.salt";
+const EXTENSION: &str = "enc";
+const NONCE_LEN: usize = 12;
+const KDF_ROUNDS: u32 = 100_000;
+
+pub struct EncryptedFsStorage {
+    dir: PathBuf,
+    key: [u8; 32],
+}
+
+impl EncryptedFsStorage {
+    // `salt_dir` is shared by all accounts so the salt survives an account being removed
+    pub fn new(dir: PathBuf, salt_dir: &Path) -> Result<Self, IloaderError> {
+        let secret = match std::env::var(PASSPHRASE_ENV) {
+            Ok(passphrase) if !passphrase.is_empty() => passphrase,
+            _ => machine_uid::get().map_err(|e| {
+                IloaderError::Storage(format!(
+                    "Failed to get machine ID, set {} instead: {}",
+                    PASSPHRASE_ENV, e
+                ))
+            })?,
+        };
+        Self::with_secret(dir, salt_dir, &secret)
+    }
+
+    fn with_secret(dir: PathBuf, salt_dir: &Path, secret: &str) -> Result<Self, IloaderError> {
+        let mut key = [0u8; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(
+            secret.as_bytes(),
+            &load_salt(salt_dir)?,
+            KDF_ROUNDS,
+            &mut key,
+        );
+
+        Ok(EncryptedFsStorage { dir, key })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, EXTENSION))
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.key))
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), IloaderError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher()
+            .encrypt(Nonce::from_slice(&nonce), value.as_bytes())
+            .map_err(|_| IloaderError::Storage(format!("Failed to encrypt {}", key)))?;
+
+        // keys can contain slashes, e.g. isideload's "<email hash>/key"
+        let path = self.path(key);
+        fs::create_dir_all(path.parent().unwrap_or(&self.dir))
+            .map_err(|e| IloaderError::Storage(format!("Failed to create storage dir: {}", e)))?;
+        fs::write(path, [nonce.as_slice(), &ciphertext].concat())
+            .map_err(|e| IloaderError::Storage(format!("Failed to write {}: {}", key, e)))
+    }
+
+    fn read(&self, key: &str) -> Result<Option<String>, IloaderError> {
+        let data = match fs::read(self.path(key)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(IloaderError::Storage(format!(
+                    "Failed to read {}: {}",
+                    key, e
+                )));
+            }
+        };
+        if data.len() < NONCE_LEN {
+            return Err(IloaderError::Storage(format!("{} is corrupted", key)));
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher()
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                IloaderError::Storage(format!(
+                    "Failed to decrypt {}, the passphrase or machine may have changed",
+                    key
+                ))
+            })?;
+        String::from_utf8(plaintext)
+            .map(Some)
+            .map_err(|_| IloaderError::Storage(format!("{} is corrupted", key)))
+    }
+
+    fn remove(&self, key: &str) -> Result<(), IloaderError> {
+        match fs::remove_file(self.path(key)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(IloaderError::Storage(
+                format!("Failed to delete {}: {}", key, e),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // Keys currently stored, for showing the user what's on disk
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys = vec![];
+        collect_keys(&self.dir, "", &mut keys);
+        keys.sort();
+        keys
+    }
+}
+
+fn collect_keys(dir: &Path, prefix: &str, keys: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() {
+            collect_keys(&path, &format!("{}{}/", prefix, name), keys);
+        } else if path.extension().is_some_and(|ext| ext == EXTENSION)
+            && let Some(stem) = path.file_stem()
+        {
+            keys.push(format!("{}{}", prefix, stem.to_string_lossy()));
+        }
+    }
+}
+
+fn load_salt(dir: &Path) -> Result<Vec<u8>, IloaderError> {
+    let path = dir.join(SALT_FILE);
+    // a salt that exists but can't be read must not be replaced, that would orphan every entry
+    match fs::read(&path) {
+        Ok(salt) => return Ok(salt),
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(IloaderError::Storage(format!(
+                "Failed to read storage salt: {}",
+                e
+            )));
+        }
+        Err(_) => {}
+    }
+
+    let mut salt = vec![0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    fs::create_dir_all(dir)
+        .map_err(|e| IloaderError::Storage(format!("Failed to create storage dir: {}", e)))?;
+    fs::write(&path, &salt)
+        .map_err(|e| IloaderError::Storage(format!("Failed to save storage salt: {}", e)))?;
+    Ok(salt)
+}
+
+impl SideloadingStorage for EncryptedFsStorage {
+    fn store(&self, key: &str, value: &str) -> Result<(), Report> {
+        self.write(key, value).map_err(|e| report!("{}", e))
+    }
+
+    fn retrieve(&self, key: &str) -> Result<Option<String>, Report> {
+        self.read(key).map_err(|e| report!("{}", e))
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Report> {
+        self.remove(key).map_err(|e| report!("{}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "correct horse battery staple";
+
+    // A fresh directory per test, holding the salt and an "account" directory
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "iloader-encrypted-{}-{}",
+            name,
+            chrono::Utc::now().timestamp_nanos_opt().unwrap()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn open(root: &Path, secret: &str) -> EncryptedFsStorage {
+        EncryptedFsStorage::with_secret(root.join("account"), root, secret).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let root = temp_dir("round-trip");
+        let storage = open(&root, SECRET);
+        storage
+            .store("anisette_state", "{\"adi_pb\":\"x\"}")
+            .unwrap();
+
+        assert_eq!(
+            storage.retrieve("anisette_state").unwrap().as_deref(),
+            Some("{\"adi_pb\":\"x\"}")
+        );
+        // reopening derives the same key from the saved salt
+        assert_eq!(
+            open(&root, SECRET)
+                .retrieve("anisette_state")
+                .unwrap()
+                .as_deref(),
+            Some("{\"adi_pb\":\"x\"}")
+        );
+        assert_eq!(storage.retrieve("missing").unwrap(), None);
+
+        storage.delete("anisette_state").unwrap();
+        assert_eq!(storage.retrieve("anisette_state").unwrap(), None);
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn values_are_not_stored_in_plaintext() {
+        let root = temp_dir("plaintext");
+        open(&root, SECRET).store("password", "hunter2").unwrap();
+
+        let raw = fs::read(root.join("account").join("password.enc")).unwrap();
+        assert!(!raw.windows(7).any(|w| w == b"hunter2"));
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn nested_keys() {
+        let root = temp_dir("nested");
+        let storage = open(&root, SECRET);
+        let key = format!("{}/key", "ab".repeat(32));
+        let der = [0x30, 0x82, 0x04, 0xbe, 0x00, 0xff];
+        storage.store_data(&key, &der).unwrap();
+        storage.store("session", "token").unwrap();
+
+        assert_eq!(storage.retrieve_data(&key).unwrap(), Some(der.to_vec()));
+        assert_eq!(storage.keys(), vec![key.clone(), "session".to_string()]);
+
+        storage.delete(&key).unwrap();
+        assert_eq!(storage.retrieve_data(&key).unwrap(), None);
+        assert_eq!(storage.keys(), vec!["session".to_string()]);
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn tampered_values_are_rejected() {
+        let root = temp_dir("tamper");
+        let storage = open(&root, SECRET);
+        storage.store("session", "token").unwrap();
+
+        let path = root.join("account").join("session.enc");
+        let mut data = fs::read(&path).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 0x01;
+        fs::write(&path, &data).unwrap();
+        assert!(storage.retrieve("session").is_err());
+
+        fs::write(&path, [0u8; 4]).unwrap();
+        assert!(storage.retrieve("session").is_err());
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn another_secret_cannot_read() {
+        let root = temp_dir("secret");
+        open(&root, SECRET).store("session", "token").unwrap();
+
+        assert!(open(&root, "another machine").retrieve("session").is_err());
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn unreadable_salt_is_not_replaced() {
+        let root = temp_dir("salt");
+        // a directory where the salt file should be can't be read as one
+        fs::create_dir_all(root.join(SALT_FILE)).unwrap();
+
+        assert!(EncryptedFsStorage::with_secret(root.join("account"), &root, SECRET).is_err());
+        assert!(root.join(SALT_FILE).is_dir());
+        let _ = fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,199 @@
+use serde::{Serialize, Serializer, ser::SerializeStruct};
+
+use crate::diagnosis::diagnose;
+
+#[derive(Debug, Clone)]
+pub enum IloaderError {
+    Auth(String),
+    TwoFactor(String),
+    Anisette(String),
+    NotLoggedIn(String),
+    DeviceNotSelected,
+    DeviceDisconnected(String),
+    Usbmuxd(String),
+    Lockdown(String),
+    AfcIo(String),
+    InstallationProxy(String),
+    Network(String),
+    QuotaExceeded(String),
+    DeveloperApi(String),
+    Storage(String),
+    Pairing(String),
+    Io(String),
+    Cancelled(String),
+    Other(String),
+}
+
+impl IloaderError {
+    // Classify an error returned by Apple's developer services
+    pub fn developer(message: impl Into<String>) -> Self {
+        Self::classify(message.into(), IloaderError::DeveloperApi)
+    }
+
+    // Classify an error from isideload's install flow, which touches both Apple and the device
+    pub fn sideload(message: impl Into<String>) -> Self {
+        Self::classify(message.into(), IloaderError::Other)
+    }
+
+    // Messages matching a known diagnosis take its kind, anything else the caller's fallback
+    fn classify(message: String, fallback: fn(String) -> Self) -> Self {
+        match diagnose(&message) {
+            Some(diagnosis) => (diagnosis.error)(message),
+            None => fallback(message),
+        }
+    }
+
+    /// Stable identifier the frontend and support docs can key off of
+    pub fn code(&self) -> &'static str {
+        match self {
+            IloaderError::Auth(_) => "AUTH",
+            IloaderError::TwoFactor(_) => "TWO_FACTOR",
+            IloaderError::Anisette(_) => "ANISETTE",
+            IloaderError::NotLoggedIn(_) => "NOT_LOGGED_IN",
+            IloaderError::DeviceNotSelected => "DEVICE_NOT_SELECTED",
+            IloaderError::DeviceDisconnected(_) => "DEVICE_DISCONNECTED",
+            IloaderError::Usbmuxd(_) => "USBMUXD",
+            IloaderError::Lockdown(_) => "LOCKDOWN",
+            IloaderError::AfcIo(_) => "AFC_IO",
+            IloaderError::InstallationProxy(_) => "INSTALLATION_PROXY",
+            IloaderError::Network(_) => "NETWORK",
+            IloaderError::QuotaExceeded(_) => "QUOTA_EXCEEDED",
+            IloaderError::DeveloperApi(_) => "DEVELOPER_API",
+            IloaderError::Storage(_) => "STORAGE",
+            IloaderError::Pairing(_) => "PAIRING",
+            IloaderError::Io(_) => "IO",
+            IloaderError::Cancelled(_) => "CANCELLED",
+            IloaderError::Other(_) => "OTHER",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            IloaderError::DeviceNotSelected => "No device selected",
+            IloaderError::Auth(m)
+            | IloaderError::TwoFactor(m)
+            | IloaderError::Anisette(m)
+            | IloaderError::NotLoggedIn(m)
+            | IloaderError::DeviceDisconnected(m)
+            | IloaderError::Usbmuxd(m)
+            | IloaderError::Lockdown(m)
+            | IloaderError::AfcIo(m)
+            | IloaderError::InstallationProxy(m)
+            | IloaderError::Network(m)
+            | IloaderError::QuotaExceeded(m)
+            | IloaderError::DeveloperApi(m)
+            | IloaderError::Storage(m)
+            | IloaderError::Pairing(m)
+            | IloaderError::Io(m)
+            | IloaderError::Cancelled(m)
+            | IloaderError::Other(m) => m,
+        }
+    }
+
+    // The known fix for this exact message if there is one, otherwise general advice for its kind
+    pub fn hint(&self) -> Option<String> {
+        if let Some(diagnosis) = diagnose(self.message()) {
+            return Some(diagnosis.steps.join(" "));
+        }
+
+        let hint = match self {
+            IloaderError::Auth(_) => Some("Check your Apple ID and password, then sign in again."),
+            IloaderError::TwoFactor(_) => {
+                Some("Sign in again and enter the verification code before the prompt times out.")
+            }
+            IloaderError::Anisette(_) => {
+                Some("Try a different anisette server in settings, or reset the anisette state.")
+            }
+            IloaderError::NotLoggedIn(_) => Some("Sign in with your Apple ID first."),
+            IloaderError::DeviceNotSelected => Some("Select a device first."),
+            IloaderError::DeviceDisconnected(_) => {
+                Some("Reconnect your device, unlock it and refresh the device list.")
+            }
+            IloaderError::Usbmuxd(_) => Some(
+                "Make sure iTunes/Apple Devices (Windows) or usbmuxd (Linux) is installed and running.",
+            ),
+            IloaderError::Lockdown(_) => {
+                Some("Unlock your device and tap \"Trust\" when asked, then try again.")
+            }
+            IloaderError::AfcIo(_) => {
+                Some("Keep the device unlocked and connected, then try again.")
+            }
+            IloaderError::Network(_) => Some("Check your internet connection and try again."),
+            IloaderError::QuotaExceeded(_) => Some(
+                "Free accounts are limited in apps, App IDs and certificates. Remove unused ones or wait for them to expire.",
+            ),
+            IloaderError::Pairing(_) => Some("Unlock your device and trust this computer again."),
+            _ => None,
+        };
+        hint.map(|h| h.to_string())
+    }
+}
+
+impl std::fmt::Display for IloaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for IloaderError {}
+
+impl Serialize for IloaderError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("IloaderError", 3)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", self.message())?;
+        state.serialize_field("hint", &self.hint())?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_known_messages() {
+        assert!(matches!(
+            IloaderError::developer(
+                "Your maximum App ID limit has been reached. You may create up to 10 App IDs every 7 days."
+            ),
+            IloaderError::QuotaExceeded(_)
+        ));
+        assert!(matches!(
+            IloaderError::sideload("Failed to start lockdown session: InvalidHostID"),
+            IloaderError::Pairing(_)
+        ));
+        assert!(matches!(
+            IloaderError::developer("Your session has expired. Please log in."),
+            IloaderError::Auth(_)
+        ));
+    }
+
+    #[test]
+    fn unknown_messages_use_the_fallback() {
+        assert!(matches!(
+            IloaderError::developer("Rate limit exceeded, try again later"),
+            IloaderError::DeveloperApi(_)
+        ));
+        assert!(matches!(
+            IloaderError::sideload("Something unexpected happened"),
+            IloaderError::Other(_)
+        ));
+    }
+
+    #[test]
+    fn hint_comes_from_the_diagnosis() {
+        let error = IloaderError::Other("Failed to connect to usbmuxd".to_string());
+        let diagnosis = diagnose(error.message()).unwrap();
+        assert_eq!(error.hint(), Some(diagnosis.steps.join(" ")));
+    }
+
+    #[test]
+    fn hint_falls_back_to_the_kind() {
+        assert_eq!(
+            IloaderError::DeviceNotSelected.hint().as_deref(),
+            Some("Select a device first.")
+        );
+        assert_eq!(IloaderError::Other("???".to_string()).hint(), None);
+    }
+}
@@ -0,0 +1,235 @@
+use serde::Serialize;
+use tauri::{AppHandle, State};
+
+use crate::{
+    device::{DeviceInfo, DeviceInfoMutex, installed_apps},
+    error::IloaderError,
+    ipa_cache::cached_ipa,
+    provisioning::{ProfileInfo, installed_profiles},
+    sideload::{SideloaderGuard, SideloaderMutex},
+    team::selected_team,
+};
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AppExpiry {
+    pub bundle_id: String,
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub team_id: Option<String>,
+    /// Whether the app was signed by the logged in account's team
+    pub signed_by_team: bool,
+    pub profile_name: Option<String>,
+    /// unix timestamp (seconds) the installed profile covering the app expires at, None when
+    /// there is none
+    pub expires_at: Option<i64>,
+    /// whole days left before the app stops launching, negative once it has expired
+    pub days_remaining: Option<i64>,
+    /// Whether iloader has the IPA the app was installed from, so it can be refreshed
+    pub refreshable: bool,
+}
+
+fn string(app: &plist::Dictionary, key: &str) -> Option<String> {
+    app.get(key)
+        .and_then(|v| v.as_string())
+        .map(|s| s.to_string())
+}
+
+// e.g. "ABCDE12345.com.example.app", taken from the entitlements the app was signed with
+fn application_identifier(app: &plist::Dictionary) -> Option<String> {
+    app.get("Entitlements")
+        .and_then(|v| v.as_dictionary())
+        .and_then(|e| e.get("application-identifier"))
+        .and_then(|v| v.as_string())
+        .map(|s| s.to_string())
+}
+
+// Profiles can cover a single App ID or a wildcard like "ABCDE12345.*"
+fn covers(profile_identifier: &str, app_identifier: &str) -> bool {
+    match profile_identifier.strip_suffix('*') {
+        Some(prefix) => app_identifier.starts_with(prefix),
+        None => profile_identifier == app_identifier,
+    }
+}
+
+// Approximates the profile the app runs under from the profiles installed on the device, since
+// installation_proxy doesn't expose the app's own embedded.mobileprovision. A profile for the
+// app's exact App ID is what iloader embeds, so the most recently issued of those wins. Only
+// without one does a wildcard profile count, the most specific and then soonest to expire, as
+// there's no telling whether the app was signed with it at all.
+fn covering_profile<'a>(
+    app_identifier: &str,
+    profiles: &'a [ProfileInfo],
+) -> Option<&'a ProfileInfo> {
+    let exact = profiles
+        .iter()
+        .filter(|p| p.application_identifier.as_deref() == Some(app_identifier))
+        .max_by_key(|p| (p.created_at, p.expires_at));
+    if exact.is_some() {
+        return exact;
+    }
+
+    profiles
+        .iter()
+        .filter_map(|p| {
+            let id = p.application_identifier.as_deref()?;
+            (id.ends_with('*') && covers(id, app_identifier)).then_some((id.len(), p))
+        })
+        .min_by_key(|(specificity, p)| {
+            (
+                std::cmp::Reverse(*specificity),
+                p.expires_at.unwrap_or(i64::MAX),
+            )
+        })
+        .map(|(_, p)| p)
+}
+
+// Every user app on `device`, `team_id`'s apps first if given and the soonest to expire at the top
+pub async fn device_app_expiry(
+    handle: &AppHandle,
+    device: &DeviceInfo,
+    team_id: Option<&str>,
+) -> Result<Vec<AppExpiry>, IloaderError> {
+    let apps = installed_apps(device).await?;
+    let profiles = installed_profiles(device).await?;
+    let now = chrono::Utc::now().timestamp();
+
+    let mut expiries: Vec<AppExpiry> = apps
+        .into_iter()
+        .filter_map(|(bundle_id, app)| {
+            let app = app.as_dictionary()?;
+            let identifier = application_identifier(app);
+            let app_team = identifier
+                .as_deref()
+                .and_then(|id| id.split_once('.'))
+                .map(|(team, _)| team.to_string());
+            let profile = identifier
+                .as_deref()
+                .and_then(|id| covering_profile(id, &profiles));
+            let expires_at = profile.and_then(|p| p.expires_at);
+            let refreshable =
+                cached_ipa(handle, &bundle_id, app_team.as_deref().unwrap_or("")).is_some();
+
+            Some(AppExpiry {
+                name: string(app, "CFBundleDisplayName").or_else(|| string(app, "CFBundleName")),
+                version: string(app, "CFBundleShortVersionString"),
+                signed_by_team: team_id.is_some() && app_team.as_deref() == team_id,
+                team_id: app_team,
+                profile_name: profile.and_then(|p| p.name.clone()),
+                expires_at,
+                days_remaining: expires_at
+                    .map(|expires| (expires - now).div_euclid(SECONDS_PER_DAY)),
+                refreshable,
+                bundle_id,
+            })
+        })
+        .collect();
+
+    expiries.sort_by_key(|a| (!a.signed_by_team, a.expires_at.unwrap_or(i64::MAX)));
+    Ok(expiries)
+}
+
+#[tauri::command]
+pub async fn app_expiry(
+    handle: AppHandle,
+    sideloader_state: State<'_, SideloaderMutex>,
+    device_state: State<'_, DeviceInfoMutex>,
+    email: Option<String>,
+) -> Result<Vec<AppExpiry>, IloaderError> {
+    let device = device_state
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(IloaderError::DeviceNotSelected)?;
+    let team_id = {
+        let mut sideloader = SideloaderGuard::take(&sideloader_state, email.as_deref())?;
+        selected_team(&handle, &mut sideloader).await?.team_id
+    };
+
+    device_app_expiry(&handle, &device, Some(&team_id)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(identifier: &str, created_at: i64, expires_at: i64) -> ProfileInfo {
+        ProfileInfo {
+            name: Some(identifier.to_string()),
+            uuid: None,
+            app_id_name: None,
+            application_identifier: Some(identifier.to_string()),
+            team_ids: vec!["ABCDE12345".to_string()],
+            platforms: vec![],
+            created_at: Some(created_at),
+            expires_at: Some(expires_at),
+            provisioned_devices: vec![],
+            entitlements: None,
+        }
+    }
+
+    const APP: &str = "ABCDE12345.com.example.app";
+
+    #[test]
+    fn exact_identifiers_cover_only_themselves() {
+        assert!(covers(APP, APP));
+        assert!(!covers(APP, "ABCDE12345.com.example.app.widget"));
+        assert!(!covers("ABCDE12345.com.example.app.widget", APP));
+        assert!(!covers(APP, "VWXYZ67890.com.example.app"));
+    }
+
+    #[test]
+    fn wildcards_cover_their_prefix() {
+        assert!(covers("ABCDE12345.*", APP));
+        assert!(covers("ABCDE12345.com.example.*", APP));
+        assert!(!covers("ABCDE12345.com.other.*", APP));
+        assert!(!covers("VWXYZ67890.*", APP));
+    }
+
+    #[test]
+    fn exact_profile_wins_over_a_longer_lived_wildcard() {
+        let profiles = vec![profile("ABCDE12345.*", 100, 10_000), profile(APP, 200, 800)];
+        let found = covering_profile(APP, &profiles).unwrap();
+        assert_eq!(found.application_identifier.as_deref(), Some(APP));
+    }
+
+    #[test]
+    fn newest_exact_profile_wins() {
+        let profiles = vec![
+            profile(APP, 100, 700),
+            profile(APP, 300, 900),
+            profile(APP, 200, 800),
+        ];
+        assert_eq!(
+            covering_profile(APP, &profiles).unwrap().created_at,
+            Some(300)
+        );
+    }
+
+    #[test]
+    fn most_specific_wildcard_then_soonest_to_expire() {
+        let profiles = vec![
+            profile("ABCDE12345.*", 100, 500),
+            profile("ABCDE12345.com.example.*", 100, 900),
+            profile("ABCDE12345.com.example.*", 100, 700),
+        ];
+        let found = covering_profile(APP, &profiles).unwrap();
+        assert_eq!(
+            found.application_identifier.as_deref(),
+            Some("ABCDE12345.com.example.*")
+        );
+        assert_eq!(found.expires_at, Some(700));
+    }
+
+    #[test]
+    fn no_covering_profile() {
+        let profiles = vec![
+            profile("ABCDE12345.com.example.app.widget", 100, 900),
+            profile("VWXYZ67890.*", 100, 900),
+        ];
+        assert!(covering_profile(APP, &profiles).is_none());
+        assert!(covering_profile(APP, &[]).is_none());
+    }
+}
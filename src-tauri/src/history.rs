@@ -0,0 +1,243 @@
+// A record of every install iloader has attempted, kept in history.json in the app data dir.
+use std::{
+    path::Path,
+    sync::atomic::{AtomicI64, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager, State, Window};
+use tauri_plugin_store::StoreExt;
+use tracing::warn;
+
+use crate::{
+    device::DeviceInfoMutex,
+    error::IloaderError,
+    ipa_cache::cached_ipa,
+    operation::Operation,
+    sideload::{SideloaderMutex, download, sideload},
+};
+
+const HISTORY_STORE: &str = "history.json";
+const MAX_RECORDS: usize = 500;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum InstallKind {
+    Sideload,
+    SideStore,
+    Refresh,
+    Rerun,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InstallRecord {
+    /// unix timestamp (milliseconds) of the install, bumped past the previous record's so it
+    /// doubles as a unique ID
+    pub id: i64,
+    pub kind: InstallKind,
+    pub device_udid: String,
+    pub device_name: String,
+    pub email: String,
+    pub team_id: Option<String>,
+    /// Local IPA path, or the URL it was downloaded from
+    pub source: String,
+    /// sha256 of the IPA that was installed
+    pub source_hash: Option<String>,
+    pub bundle_id: Option<String>,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+pub fn file_hash(path: &Path) -> Option<String> {
+    let data = std::fs::read(path).ok()?;
+    Some(
+        Sha256::digest(&data)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect(),
+    )
+}
+
+static LAST_RECORD_ID: AtomicI64 = AtomicI64::new(0);
+
+// The current time in milliseconds, or one past the last ID handed out when installs finish
+// within the same millisecond
+pub fn next_record_id() -> i64 {
+    let now = chrono::Utc::now().timestamp_millis();
+    let last = LAST_RECORD_ID
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+            Some(now.max(last + 1))
+        })
+        .unwrap_or_default();
+    now.max(last + 1)
+}
+
+fn load_history(app: &AppHandle) -> Vec<InstallRecord> {
+    app.store(HISTORY_STORE)
+        .ok()
+        .and_then(|store| store.get("installs"))
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+fn save_history(app: &AppHandle, records: &[InstallRecord]) -> Result<(), IloaderError> {
+    let store = app
+        .store(HISTORY_STORE)
+        .map_err(|e| IloaderError::Storage(format!("Failed to get store: {:?}", e)))?;
+    store.set(
+        "installs",
+        serde_json::to_value(records)
+            .map_err(|e| IloaderError::Storage(format!("Failed to serialize history: {}", e)))?,
+    );
+    Ok(())
+}
+
+// History is best effort; failing to write it never fails the install it describes
+pub fn record_install(app: &AppHandle, record: InstallRecord) {
+    let mut records = load_history(app);
+    records.push(record);
+    if records.len() > MAX_RECORDS {
+        records.drain(..records.len() - MAX_RECORDS);
+    }
+    if let Err(e) = save_history(app, &records) {
+        warn!("Failed to save install history: {}", e);
+    }
+}
+
+// The latest successful install of each app iloader has put on a device
+pub fn installed_on(app: &AppHandle, device_udid: &str) -> Vec<InstallRecord> {
+    let mut latest: Vec<InstallRecord> = vec![];
+    for record in load_history(app).into_iter().rev() {
+        if record.success
+            && record.device_udid == device_udid
+            && record.bundle_id.is_some()
+            && !latest.iter().any(|r| r.bundle_id == record.bundle_id)
+        {
+            latest.push(record);
+        }
+    }
+    latest
+}
+
+// Newest first, optionally only installs of one app or to one device
+#[tauri::command]
+pub fn install_history(
+    handle: AppHandle,
+    bundle_id: Option<String>,
+    device_udid: Option<String>,
+    limit: Option<usize>,
+) -> Vec<InstallRecord> {
+    load_history(&handle)
+        .into_iter()
+        .rev()
+        .filter(|r| bundle_id.is_none() || r.bundle_id == bundle_id)
+        .filter(|r| {
+            device_udid
+                .as_deref()
+                .is_none_or(|udid| r.device_udid == udid)
+        })
+        .take(limit.unwrap_or(usize::MAX))
+        .collect()
+}
+
+#[tauri::command]
+pub fn clear_install_history(handle: AppHandle) -> Result<(), IloaderError> {
+    save_history(&handle, &[])
+}
+
+// Find the IPA a past install used: the original file if it's unchanged, otherwise the cached
+// copy of the same build, otherwise a fresh download of its URL.
+async fn history_ipa(handle: &AppHandle, record: &InstallRecord) -> Result<String, IloaderError> {
+    let unchanged = |path: &Path| {
+        path.is_file() && (record.source_hash.is_none() || file_hash(path) == record.source_hash)
+    };
+
+    let source = Path::new(&record.source);
+    if unchanged(source) {
+        return Ok(record.source.clone());
+    }
+    if let Some(bundle_id) = &record.bundle_id
+        && let Some(cached) = cached_ipa(handle, bundle_id, record.team_id.as_deref().unwrap_or(""))
+        && unchanged(&cached)
+    {
+        return Ok(cached.to_string_lossy().to_string());
+    }
+    if record.source.starts_with("https://") || record.source.starts_with("http://") {
+        let dest = handle
+            .path()
+            .temp_dir()
+            .map_err(|e| IloaderError::Io(format!("Failed to get temp dir: {:?}", e)))?
+            .join(format!("iloader-rerun-{}.ipa", record.id));
+        download(&record.source, &dest).await?;
+        return Ok(dest.to_string_lossy().to_string());
+    }
+
+    Err(IloaderError::Io(format!(
+        "{} is no longer available or has changed since it was installed",
+        record.source
+    )))
+}
+
+// Install the same IPA again to the selected device, reported as a sideload operation
+#[tauri::command]
+pub async fn rerun_install_operation(
+    handle: AppHandle,
+    window: Window,
+    device_state: State<'_, DeviceInfoMutex>,
+    sideloader_state: State<'_, SideloaderMutex>,
+    id: i64,
+    email: Option<String>,
+) -> Result<(), IloaderError> {
+    let op = Operation::new("sideload".to_string(), &window);
+    op.start("install")?;
+    let record = op.fail_if_err(
+        "install",
+        load_history(&handle)
+            .into_iter()
+            .find(|r| r.id == id)
+            .ok_or_else(|| IloaderError::Other(format!("Install {} not found in history", id))),
+    )?;
+    let ipa = op.fail_if_err("install", history_ipa(&handle, &record).await)?;
+    op.fail_if_err(
+        "install",
+        sideload(
+            &handle,
+            &device_state,
+            &sideloader_state,
+            ipa,
+            InstallKind::Rerun,
+            Some(record.source),
+            email.or(Some(record.email)),
+        )
+        .await,
+    )?;
+    op.complete("install")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_ids_are_unique_and_increasing() {
+        let ids: Vec<i64> = (0..1000).map(|_| next_record_id()).collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn record_ids_are_unique_across_threads() {
+        let handles: Vec<_> = (0..4)
+            .map(|_| std::thread::spawn(|| (0..250).map(|_| next_record_id()).collect::<Vec<_>>()))
+            .collect();
+        let mut ids: Vec<i64> = handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 1000);
+    }
+}
@@ -0,0 +1,93 @@
+// Copies of the IPAs iloader has installed, keyed by the bundle ID in their Info.plist. Apps can't
+// be pulled back off a device without a jailbreak, so refreshing re-signs these copies instead.
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use tauri::{AppHandle, Manager};
+
+use crate::error::IloaderError;
+
+fn cache_dir(app: &AppHandle) -> Result<PathBuf, IloaderError> {
+    Ok(app
+        .path()
+        .app_data_dir()
+        .map_err(|e| IloaderError::Io(format!("Failed to get app data dir: {:?}", e)))?
+        .join("ipas"))
+}
+
+// Bundle IDs come from the IPA itself, so keep them from escaping the cache directory
+fn cache_path(dir: &Path, bundle_id: &str) -> PathBuf {
+    let name: String = bundle_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    dir.join(format!("{}.ipa", name))
+}
+
+pub fn ipa_bundle_id(path: &Path) -> Result<String, IloaderError> {
+    let file = File::open(path)
+        .map_err(|e| IloaderError::Io(format!("Failed to open {}: {}", path.display(), e)))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|e| IloaderError::Other(format!("Invalid IPA: {}", e)))?;
+
+    // Payload/<name>.app/Info.plist, not one belonging to a framework or extension
+    let info_path = archive
+        .file_names()
+        .find(|name| {
+            name.starts_with("Payload/")
+                && name.ends_with(".app/Info.plist")
+                && name.matches('/').count() == 2
+        })
+        .map(|name| name.to_string())
+        .ok_or_else(|| IloaderError::Other("IPA has no app in its Payload".to_string()))?;
+
+    let mut data = vec![];
+    archive
+        .by_name(&info_path)
+        .and_then(|mut entry| entry.read_to_end(&mut data).map_err(Into::into))
+        .map_err(|e| IloaderError::Other(format!("Failed to read Info.plist: {}", e)))?;
+    let info: plist::Dictionary = plist::from_bytes(&data)
+        .map_err(|e| IloaderError::Other(format!("Invalid Info.plist: {}", e)))?;
+
+    info.get("CFBundleIdentifier")
+        .and_then(|v| v.as_string())
+        .map(|s| s.to_string())
+        .ok_or_else(|| IloaderError::Other("Info.plist has no bundle identifier".to_string()))
+}
+
+// Keep a copy of an IPA that was just installed, returning its bundle ID
+pub fn cache_ipa(app: &AppHandle, path: &Path) -> Result<String, IloaderError> {
+    let bundle_id = ipa_bundle_id(path)?;
+    let dir = cache_dir(app)?;
+    let dest = cache_path(&dir, &bundle_id);
+    if dest != path {
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| IloaderError::Io(format!("Failed to create IPA cache: {}", e)))?;
+        std::fs::copy(path, &dest)
+            .map_err(|e| IloaderError::Io(format!("Failed to cache IPA: {}", e)))?;
+    }
+    Ok(bundle_id)
+}
+
+// Free accounts can't register another team's bundle ID, so apps get installed with the team ID
+// appended; both forms map back to the same cached IPA.
+pub fn cached_ipa(app: &AppHandle, installed_bundle_id: &str, team_id: &str) -> Option<PathBuf> {
+    let dir = cache_dir(app).ok()?;
+    let original = installed_bundle_id
+        .strip_suffix(team_id)
+        .and_then(|id| id.strip_suffix('.'));
+
+    std::iter::once(installed_bundle_id)
+        .chain(original)
+        .map(|id| cache_path(&dir, id))
+        .find(|path| path.is_file())
+}
@@ -1,22 +1,68 @@
 #[macro_use]
 mod account;
+mod anisette;
+mod app_id;
+mod auto_refresh;
+mod certificate;
 #[macro_use]
 mod device;
 #[macro_use]
 mod sideload;
 #[macro_use]
 mod pairing;
+mod diagnosis;
+mod encrypted_storage;
+mod error;
+mod expiry;
+mod history;
+mod ipa_cache;
 mod logging;
+mod metadata;
 mod operation;
+mod provisioning;
+mod refresh;
+mod registered_device;
+mod session;
+mod storage;
+mod team;
+mod two_factor;
 
 use crate::{
     account::{
-        delete_account, delete_app_id, get_certificates, invalidate_account, list_app_ids,
-        logged_in_as, login_new, login_stored, reset_anisette_state, revoke_certificate,
+        delete_account, invalidate_account, logged_in_accounts, logged_in_as, login_new,
+        login_stored, resume_session, switch_account,
+    },
+    anisette::{check_anisette_servers, get_anisette_config, set_anisette_config},
+    app_id::{delete_app_id, delete_app_ids, list_app_ids},
+    auto_refresh::{
+        SystemClock, auto_refresh_config, auto_refresh_now, get_auto_refresh_config, hide_on_close,
+        run_scheduler, set_auto_refresh_config, set_tray_labels, sync_tray,
+    },
+    certificate::{
+        export_certificate, get_certificates, get_max_certs_policy, import_certificate,
+        revoke_certificate, revoke_certificates_operation, set_max_certs_policy,
     },
     device::{DeviceInfoMutex, list_devices, set_selected_device},
-    pairing::{export_pairing_cmd, installed_pairing_apps, place_pairing_cmd},
-    sideload::{SideloaderMutex, install_sidestore_operation, sideload_operation},
+    diagnosis::diagnose_error,
+    expiry::app_expiry,
+    history::{clear_install_history, install_history, rerun_install_operation},
+    metadata::{export_accounts, import_accounts, list_accounts, rename_account, reorder_accounts},
+    pairing::{
+        convert_pairing_file, export_all_pairings_to, export_pairing_cmd, export_pairing_to,
+        inspect_pairing_file, installed_pairing_apps, place_pairing_cmd,
+    },
+    provisioning::{
+        device_provisioning_profiles, download_provisioning_profile, inspect_provisioning_profile,
+        list_provisioning_profiles,
+    },
+    refresh::refresh_apps_operation,
+    registered_device::{list_registered_devices, register_device},
+    sideload::{SideloaderMutex, Sideloaders, install_sidestore_operation, sideload_operation},
+    storage::{
+        diagnose_storage, list_stored_data, migrate_account_storage, reset_anisette_state,
+        storage_backend,
+    },
+    team::{list_teams, set_team},
 };
 use tauri::Manager;
 use tracing_subscriber::{Layer, Registry, fmt, layer::SubscriberExt, util::SubscriberInitExt};
@@ -90,14 +136,24 @@ pub fn run() {
             }));
 
             app.manage(DeviceInfoMutex::new(None));
-            app.manage(SideloaderMutex::new(None));
+            app.manage(SideloaderMutex::new(Sideloaders::default()));
+
+            // the app works without a tray icon, closing the window just quits it then
+            if let Err(e) = sync_tray(app.handle(), &auto_refresh_config(app.handle())) {
+                tracing::warn!("Failed to create the tray icon: {}", e);
+            }
+            tauri::async_runtime::spawn(run_scheduler(app.handle().clone(), SystemClock));
             Ok(())
         })
+        .on_window_event(hide_on_close)
         .invoke_handler(tauri::generate_handler![
             login_new,
             invalidate_account,
             logged_in_as,
+            logged_in_accounts,
+            switch_account,
             login_stored,
+            resume_session,
             delete_account,
             list_devices,
             sideload_operation,
@@ -105,12 +161,52 @@ pub fn run() {
             install_sidestore_operation,
             get_certificates,
             revoke_certificate,
+            revoke_certificates_operation,
+            export_certificate,
+            import_certificate,
+            get_max_certs_policy,
+            set_max_certs_policy,
             list_app_ids,
             delete_app_id,
+            delete_app_ids,
+            list_registered_devices,
+            register_device,
+            list_provisioning_profiles,
+            download_provisioning_profile,
+            inspect_provisioning_profile,
+            device_provisioning_profiles,
+            app_expiry,
+            refresh_apps_operation,
+            install_history,
+            clear_install_history,
+            rerun_install_operation,
             installed_pairing_apps,
             place_pairing_cmd,
             reset_anisette_state,
+            list_stored_data,
+            storage_backend,
+            migrate_account_storage,
+            diagnose_storage,
+            list_accounts,
+            rename_account,
+            reorder_accounts,
+            export_accounts,
+            import_accounts,
+            check_anisette_servers,
+            get_anisette_config,
+            set_anisette_config,
             export_pairing_cmd,
+            export_pairing_to,
+            export_all_pairings_to,
+            list_teams,
+            set_team,
+            inspect_pairing_file,
+            convert_pairing_file,
+            diagnose_error,
+            get_auto_refresh_config,
+            set_auto_refresh_config,
+            auto_refresh_now,
+            set_tray_labels,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
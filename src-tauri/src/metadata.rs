@@ -0,0 +1,270 @@
+use isideload::dev::teams::DeveloperTeam;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+use crate::{
+    anisette::{AnisetteConfig, anisette_config, save_anisette_config},
+    certificate::MaxCertsPolicy,
+    error::IloaderError,
+    team::{save_team_id, saved_team_id},
+};
+
+const EXPORT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum AccountType {
+    Free,
+    Paid,
+}
+
+// Everything iloader remembers about a saved account apart from its credentials. Kept in
+// data.json as a map of account email -> metadata, next to the "ids" list that orders them.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AccountMetadata {
+    pub label: Option<String>,
+    pub anisette_server: Option<String>,
+    /// unix timestamp (seconds) of the last successful login
+    pub last_login: Option<i64>,
+    pub certificate_serial: Option<String>,
+    /// machine ID the certificate in use was registered with
+    pub machine_id: Option<String>,
+    pub account_type: Option<AccountType>,
+    pub max_certs_policy: Option<MaxCertsPolicy>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountEntry {
+    pub email: String,
+    pub team_id: Option<String>,
+    #[serde(flatten)]
+    pub metadata: AccountMetadata,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportedAccount {
+    #[serde(flatten)]
+    entry: AccountEntry,
+    anisette: AnisetteConfig,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccountsExport {
+    version: u32,
+    accounts: Vec<ExportedAccount>,
+}
+
+fn store_error(e: impl std::fmt::Debug) -> IloaderError {
+    IloaderError::Storage(format!("Failed to get store: {:?}", e))
+}
+
+pub fn saved_ids(app: &AppHandle) -> Vec<String> {
+    app.store("data.json")
+        .ok()
+        .and_then(|store| store.get("ids"))
+        .and_then(|ids| serde_json::from_value(ids).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_ids(app: &AppHandle, ids: &[String]) -> Result<(), IloaderError> {
+    let store = app.store("data.json").map_err(store_error)?;
+    store.set(
+        "ids",
+        Value::Array(ids.iter().map(|id| Value::String(id.clone())).collect()),
+    );
+    Ok(())
+}
+
+fn all_metadata(app: &AppHandle) -> Map<String, Value> {
+    app.store("data.json")
+        .ok()
+        .and_then(|store| store.get("accounts"))
+        .and_then(|v| v.as_object().cloned())
+        .unwrap_or_else(Map::new)
+}
+
+pub fn account_metadata(app: &AppHandle, email: &str) -> AccountMetadata {
+    all_metadata(app)
+        .get(&email.to_lowercase())
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default()
+}
+
+fn save_metadata(
+    app: &AppHandle,
+    email: &str,
+    metadata: &AccountMetadata,
+) -> Result<(), IloaderError> {
+    let store = app.store("data.json").map_err(store_error)?;
+    let mut accounts = all_metadata(app);
+    accounts.insert(
+        email.to_lowercase(),
+        serde_json::to_value(metadata)
+            .map_err(|e| IloaderError::Storage(format!("Failed to serialize metadata: {}", e)))?,
+    );
+    store.set("accounts", Value::Object(accounts));
+    Ok(())
+}
+
+pub fn update_metadata(
+    app: &AppHandle,
+    email: &str,
+    update: impl FnOnce(&mut AccountMetadata),
+) -> Result<(), IloaderError> {
+    let mut metadata = account_metadata(app, email);
+    update(&mut metadata);
+    save_metadata(app, email, &metadata)
+}
+
+pub fn remove_metadata(app: &AppHandle, email: &str) -> Result<(), IloaderError> {
+    let store = app.store("data.json").map_err(store_error)?;
+    let mut accounts = all_metadata(app);
+    accounts.remove(&email.to_lowercase());
+    store.set("accounts", Value::Object(accounts));
+    Ok(())
+}
+
+// Apple doesn't say outright whether an account is paid; free provisioning teams have a type of
+// their own, while paid teams report the program membership they belong to.
+fn account_type(team: &DeveloperTeam) -> Option<AccountType> {
+    let team_type = team.r#type.as_deref()?.to_lowercase();
+    if team_type.contains("free") {
+        Some(AccountType::Free)
+    } else if team_type.contains("company")
+        || team_type.contains("individual")
+        || team_type.contains("enterprise")
+    {
+        Some(AccountType::Paid)
+    } else {
+        None
+    }
+}
+
+pub fn record_login(
+    app: &AppHandle,
+    email: &str,
+    team: &DeveloperTeam,
+) -> Result<(), IloaderError> {
+    update_metadata(app, email, |metadata| {
+        metadata.last_login = Some(chrono::Utc::now().timestamp());
+        metadata.account_type = account_type(team).or(metadata.account_type);
+    })
+}
+
+fn account_entry(app: &AppHandle, email: &str) -> AccountEntry {
+    AccountEntry {
+        email: email.to_string(),
+        team_id: saved_team_id(app, email),
+        metadata: account_metadata(app, email),
+    }
+}
+
+#[tauri::command]
+pub fn list_accounts(handle: AppHandle) -> Vec<AccountEntry> {
+    saved_ids(&handle)
+        .iter()
+        .map(|email| account_entry(&handle, email))
+        .collect()
+}
+
+#[tauri::command]
+pub fn rename_account(
+    handle: AppHandle,
+    email: String,
+    label: Option<String>,
+) -> Result<(), IloaderError> {
+    update_metadata(&handle, &email, |metadata| {
+        metadata.label = label.filter(|l| !l.trim().is_empty());
+    })
+}
+
+// Reorder the saved accounts; emails missing from `emails` keep their relative order at the end
+#[tauri::command]
+pub fn reorder_accounts(handle: AppHandle, emails: Vec<String>) -> Result<(), IloaderError> {
+    let existing = saved_ids(&handle);
+    let mut ordered: Vec<String> = emails
+        .into_iter()
+        .filter(|email| existing.contains(email))
+        .collect();
+    for email in existing {
+        if !ordered.contains(&email) {
+            ordered.push(email);
+        }
+    }
+    save_ids(&handle, &ordered)
+}
+
+// Write every saved account's metadata, team and anisette settings to a file. Passwords and
+// cached sessions are never exported.
+#[tauri::command]
+pub fn export_accounts(handle: AppHandle, path: String) -> Result<usize, IloaderError> {
+    let accounts: Vec<ExportedAccount> = saved_ids(&handle)
+        .iter()
+        .map(|email| ExportedAccount {
+            entry: account_entry(&handle, email),
+            anisette: anisette_config(&handle, email),
+        })
+        .collect();
+    let count = accounts.len();
+
+    let json = serde_json::to_string_pretty(&AccountsExport {
+        version: EXPORT_VERSION,
+        accounts,
+    })
+    .map_err(|e| IloaderError::Other(format!("Failed to serialize accounts: {}", e)))?;
+    std::fs::write(&path, json)
+        .map_err(|e| IloaderError::Io(format!("Failed to write {}: {}", path, e)))?;
+    Ok(count)
+}
+
+// Merge accounts from an export into this machine's list. Imported accounts still need to be
+// signed in once since no passwords are included.
+#[tauri::command]
+pub fn import_accounts(handle: AppHandle, path: String) -> Result<Vec<String>, IloaderError> {
+    let json = std::fs::read_to_string(&path)
+        .map_err(|e| IloaderError::Io(format!("Failed to read {}: {}", path, e)))?;
+    let export: AccountsExport = serde_json::from_str(&json)
+        .map_err(|e| IloaderError::Other(format!("Invalid account export: {}", e)))?;
+    if export.version > EXPORT_VERSION {
+        return Err(IloaderError::Other(format!(
+            "Account export version {} is newer than this version of iloader supports",
+            export.version
+        )));
+    }
+
+    let mut ids = saved_ids(&handle);
+    let mut imported = vec![];
+    for account in export.accounts {
+        let email = account.entry.email;
+        // this machine's login history and certificate are not the exporter's
+        let existing = account_metadata(&handle, &email);
+        save_metadata(
+            &handle,
+            &email,
+            &AccountMetadata {
+                last_login: existing.last_login,
+                certificate_serial: existing.certificate_serial,
+                machine_id: existing.machine_id,
+                ..account.entry.metadata
+            },
+        )?;
+        if let Some(team_id) = &account.entry.team_id {
+            save_team_id(&handle, &email, team_id)?;
+        }
+        save_anisette_config(&handle, &email, account.anisette)?;
+
+        if !ids.contains(&email) {
+            ids.push(email.clone());
+        }
+        imported.push(email);
+    }
+    save_ids(&handle, &ids)?;
+
+    Ok(imported)
+}
@@ -1,6 +1,11 @@
 use serde::Serialize;
 use tauri::{Emitter, Window};
 
+use crate::{
+    diagnosis::{Diagnosis, diagnose},
+    error::IloaderError,
+};
+
 pub struct Operation<'a> {
     id: String,
     window: &'a Window,
@@ -12,6 +17,7 @@ struct OperationUpdate<'a> {
     update_type: &'a str,
     step_id: &'a str,
     extra_details: Option<String>,
+    diagnosis: Option<&'static Diagnosis>,
 }
 
 impl<'a> Operation<'a> {
@@ -19,12 +25,12 @@ impl<'a> Operation<'a> {
         Operation { id, window }
     }
 
-    pub fn move_on(&self, old_id: &str, new_id: &str) -> Result<(), String> {
+    pub fn move_on(&self, old_id: &str, new_id: &str) -> Result<(), IloaderError> {
         self.complete(old_id)?;
         self.start(new_id)
     }
 
-    pub fn start(&self, id: &str) -> Result<(), String> {
+    pub fn start(&self, id: &str) -> Result<(), IloaderError> {
         self.window
             .emit(
                 &format!("operation_{}", self.id),
@@ -32,12 +38,13 @@ impl<'a> Operation<'a> {
                     update_type: "started",
                     step_id: id,
                     extra_details: None,
+                    diagnosis: None,
                 },
             )
-            .map_err(|_| "Failed to emit status to frontend".to_string())
+            .map_err(|_| IloaderError::Other("Failed to emit status to frontend".to_string()))
     }
 
-    pub fn complete(&self, id: &str) -> Result<(), String> {
+    pub fn complete(&self, id: &str) -> Result<(), IloaderError> {
         self.window
             .emit(
                 &format!("operation_{}", self.id),
@@ -45,26 +52,48 @@ impl<'a> Operation<'a> {
                     update_type: "finished",
                     step_id: id,
                     extra_details: None,
+                    diagnosis: None,
+                },
+            )
+            .map_err(|_| IloaderError::Other("Failed to emit status to frontend".to_string()))
+    }
+
+    // Report how a long-running step is getting on without finishing it
+    pub fn progress(&self, id: &str, details: String) -> Result<(), IloaderError> {
+        self.window
+            .emit(
+                &format!("operation_{}", self.id),
+                OperationUpdate {
+                    update_type: "progress",
+                    step_id: id,
+                    extra_details: Some(details),
+                    diagnosis: None,
                 },
             )
-            .map_err(|_| "Failed to emit status to frontend".to_string())
+            .map_err(|_| IloaderError::Other("Failed to emit status to frontend".to_string()))
     }
 
-    pub fn fail<T>(&self, id: &str, error: String) -> Result<T, String> {
+    pub fn fail<T>(&self, id: &str, error: impl Into<IloaderError>) -> Result<T, IloaderError> {
+        let error = error.into();
         self.window
             .emit(
                 &format!("operation_{}", self.id),
                 OperationUpdate {
                     update_type: "failed",
                     step_id: id,
-                    extra_details: Some(error.clone()),
+                    extra_details: Some(error.to_string()),
+                    diagnosis: diagnose(error.message()),
                 },
             )
-            .map_err(|_| "Failed to emit status to frontend".to_string())?;
+            .map_err(|_| IloaderError::Other("Failed to emit status to frontend".to_string()))?;
         Err(error)
     }
 
-    pub fn fail_if_err<T>(&self, id: &str, res: Result<T, String>) -> Result<T, String> {
+    pub fn fail_if_err<T, E: Into<IloaderError>>(
+        &self,
+        id: &str,
+        res: Result<T, E>,
+    ) -> Result<T, IloaderError> {
         match res {
             Ok(t) => Ok(t),
             Err(e) => self.fail::<T>(id, e),
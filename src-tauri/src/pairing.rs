@@ -1,15 +1,23 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 // used https://github.com/jkcoxson/idevice_pair/ as a guide
 use idevice::{
     IdeviceService, house_arrest::HouseArrestClient, installation_proxy::InstallationProxyClient,
     lockdown::LockdownClient, pairing_file::PairingFile, usbmuxd::UsbmuxdConnection,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, State};
 use tauri_plugin_dialog::DialogExt;
 
-use crate::device::{DeviceInfo, DeviceInfoMutex, get_provider, get_provider_from_connection};
+use crate::{
+    device::{
+        DeviceInfo, DeviceInfoMutex, get_provider, get_provider_from_connection, list_devices,
+    },
+    error::IloaderError,
+};
 
 const PAIRING_APPS: &[(&str, &str)] = &[
     ("SideStore", "ALTPairingFile.mobiledevicepairing"),
@@ -31,25 +39,25 @@ const PAIRING_APPS: &[(&str, &str)] = &[
 async fn pairing_file(
     device: DeviceInfo,
     usbmuxd: &mut UsbmuxdConnection,
-) -> Result<PairingFile, String> {
+) -> Result<PairingFile, IloaderError> {
     let provider = get_provider(&device).await?;
 
     let mut pairing_file = usbmuxd.get_pair_record(&provider.udid).await.map_err(|e| {
-        format!(
+        IloaderError::Pairing(format!(
             "Failed to get pairing record for device {}: {}",
             device.name, e
-        )
+        ))
     })?;
 
     pairing_file.udid = Some(provider.udid.clone());
 
     let mut lc = LockdownClient::connect(&provider)
         .await
-        .map_err(|e| format!("Failed to connect to lockdown: {}", e))?;
+        .map_err(|e| IloaderError::Lockdown(format!("Failed to connect to lockdown: {}", e)))?;
 
     lc.start_session(&pairing_file)
         .await
-        .map_err(|e| format!("Failed to start lockdown session: {}", e))?;
+        .map_err(|e| IloaderError::Lockdown(format!("Failed to start lockdown session: {}", e)))?;
 
     lc.set_value(
         "EnableWifiDebugging",
@@ -57,7 +65,7 @@ async fn pairing_file(
         Some("com.apple.mobile.wireless_lockdown"),
     )
     .await
-    .map_err(|e| format!("Failed to enable wifi debugging: {}", e))?;
+    .map_err(|e| IloaderError::Lockdown(format!("Failed to enable wifi debugging: {}", e)))?;
 
     Ok(pairing_file)
 }
@@ -66,10 +74,10 @@ pub async fn place_pairing(
     device: DeviceInfo,
     bundle_id: String,
     path: String,
-) -> Result<(), String> {
+) -> Result<(), IloaderError> {
     let mut usbmuxd = UsbmuxdConnection::default()
         .await
-        .map_err(|e| format!("Failed to connect to usbmuxd: {}", e))?;
+        .map_err(|e| IloaderError::Usbmuxd(format!("Failed to connect to usbmuxd: {}", e)))?;
 
     let provider = get_provider_from_connection(&device, &mut usbmuxd).await?;
 
@@ -77,12 +85,12 @@ pub async fn place_pairing(
 
     let house_arrest_client = HouseArrestClient::connect(&provider)
         .await
-        .map_err(|e| format!("Failed to connect to house arrest: {}", e))?;
+        .map_err(|e| IloaderError::AfcIo(format!("Failed to connect to house arrest: {}", e)))?;
 
     let mut afc_client = house_arrest_client
         .vend_documents(bundle_id)
         .await
-        .map_err(|e| format!("Failed to vend documents: {}", e))?;
+        .map_err(|e| IloaderError::AfcIo(format!("Failed to vend documents: {}", e)))?;
 
     afc_client
         .mk_dir(format!(
@@ -90,7 +98,7 @@ pub async fn place_pairing(
             path.rsplit_once('/').map(|x| x.0).unwrap_or("")
         ))
         .await
-        .map_err(|e| format!("Failed to create Documents directory: {}", e))?;
+        .map_err(|e| IloaderError::AfcIo(format!("Failed to create Documents directory: {}", e)))?;
 
     let mut file = afc_client
         .open(
@@ -98,18 +106,18 @@ pub async fn place_pairing(
             idevice::afc::opcode::AfcFopenMode::Wr,
         )
         .await
-        .map_err(|e| format!("Failed to open file on device: {}", e))?;
+        .map_err(|e| IloaderError::AfcIo(format!("Failed to open file on device: {}", e)))?;
 
     file.write_entire(
-        &pairing_file
-            .serialize()
-            .map_err(|e| format!("Failed to serialize pairing file: {}", e))?,
+        &pairing_file.serialize().map_err(|e| {
+            IloaderError::Pairing(format!("Failed to serialize pairing file: {}", e))
+        })?,
     )
     .await
-    .map_err(|e| format!("Failed to write pairing file: {}", e))?;
+    .map_err(|e| IloaderError::AfcIo(format!("Failed to write pairing file: {}", e)))?;
     file.close()
         .await
-        .map_err(|e| format!("Failed to close file: {}", e))?;
+        .map_err(|e| IloaderError::AfcIo(format!("Failed to close file: {}", e)))?;
 
     Ok(())
 }
@@ -119,40 +127,54 @@ pub async fn place_pairing_cmd(
     device_state: State<'_, DeviceInfoMutex>,
     bundle_id: String,
     path: String,
-) -> Result<(), String> {
+) -> Result<(), IloaderError> {
     let device = {
         let device_guard = device_state.lock().unwrap();
         match &*device_guard {
             Some(d) => d.clone(),
-            None => return Err("No device selected".to_string()),
+            None => return Err(IloaderError::DeviceNotSelected),
         }
     };
 
     place_pairing(device, bundle_id, path).await
 }
 
+pub async fn export_pairing(
+    device: DeviceInfo,
+    path: impl AsRef<Path>,
+    format: &PairingFormat,
+) -> Result<(), IloaderError> {
+    let pairing_file = {
+        let mut usbmuxd = UsbmuxdConnection::default()
+            .await
+            .map_err(|e| IloaderError::Usbmuxd(format!("Failed to connect to usbmuxd: {}", e)))?;
+
+        pairing_file(device, &mut usbmuxd).await?
+    };
+
+    let serialized = pairing_file
+        .serialize()
+        .map_err(|e| IloaderError::Pairing(format!("Failed to serialize pairing file: {}", e)))?;
+
+    tokio::fs::write(path, convert_pairing(&serialized, format)?)
+        .await
+        .map_err(|e| IloaderError::Io(format!("Failed to write pairing file: {}", e)))
+}
+
 // prompt for a location to save the pairing file, then export it there. This is for advanced users who want to use the pairing file with other tools, or just want a backup of it. Normal users should use the "Place" button next to the app they want to pair with instead, which will transfer the pairing file automatically.
 #[tauri::command]
 pub async fn export_pairing_cmd(
     device_state: State<'_, DeviceInfoMutex>,
     app: AppHandle,
-) -> Result<(), String> {
+) -> Result<(), IloaderError> {
     let device = {
         let device_guard = device_state.lock().unwrap();
         match &*device_guard {
             Some(d) => d.clone(),
-            None => return Err("No device selected".to_string()),
+            None => return Err(IloaderError::DeviceNotSelected),
         }
     };
 
-    let pairing_file = {
-        let mut usbmuxd = UsbmuxdConnection::default()
-            .await
-            .map_err(|e| format!("Failed to connect to usbmuxd: {}", e))?;
-
-        pairing_file(device, &mut usbmuxd).await?
-    };
-
     let save_path = app
         .dialog()
         .file()
@@ -164,19 +186,63 @@ pub async fn export_pairing_cmd(
     if let Some(save_path) = save_path
         && let Some(save_path) = save_path.as_path()
     {
-        tokio::fs::write(
-            save_path,
-            &pairing_file
-                .serialize()
-                .map_err(|e| format!("Failed to serialize pairing file: {}", e))?,
-        )
-        .await
-        .map_err(|e| format!("Failed to write pairing file: {}", e))?;
-
-        Ok(())
+        export_pairing(device, save_path, &PairingFormat::Xml).await
     } else {
-        Err("Save cancelled".to_string())
+        Err(IloaderError::Cancelled("Save cancelled".to_string()))
+    }
+}
+
+#[tauri::command]
+pub async fn export_pairing_to(
+    device_state: State<'_, DeviceInfoMutex>,
+    path: String,
+    format: Option<PairingFormat>,
+) -> Result<(), IloaderError> {
+    let device = {
+        let device_guard = device_state.lock().unwrap();
+        match &*device_guard {
+            Some(d) => d.clone(),
+            None => return Err(IloaderError::DeviceNotSelected),
+        }
+    };
+
+    export_pairing(device, path, &format.unwrap_or(PairingFormat::Xml)).await
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PairingExportResult {
+    pub udid: String,
+    pub path: Option<String>,
+    pub error: Option<IloaderError>,
+}
+
+// export a pairing file for every connected device into a directory, named after each device's UDID
+#[tauri::command]
+pub async fn export_all_pairings_to(
+    directory: String,
+    format: Option<PairingFormat>,
+) -> Result<Vec<PairingExportResult>, IloaderError> {
+    let format = format.unwrap_or(PairingFormat::Xml);
+    let extension = format.extension()?;
+    let directory = PathBuf::from(directory);
+    tokio::fs::create_dir_all(&directory)
+        .await
+        .map_err(|e| IloaderError::Io(format!("Failed to create export directory: {}", e)))?;
+
+    let mut results = Vec::new();
+    for device in list_devices().await? {
+        let udid = device.uuid.clone();
+        let path = directory.join(format!("{}.{}", udid, extension));
+        let result = export_pairing(device, &path, &format).await;
+        results.push(PairingExportResult {
+            udid,
+            path: result.is_ok().then(|| path.to_string_lossy().to_string()),
+            error: result.err(),
+        });
     }
+
+    Ok(results)
 }
 
 #[derive(Serialize)]
@@ -190,30 +256,40 @@ pub struct PairingAppInfo {
 #[tauri::command]
 pub async fn installed_pairing_apps(
     device_state: State<'_, DeviceInfoMutex>,
-) -> Result<Vec<PairingAppInfo>, String> {
+) -> Result<Vec<PairingAppInfo>, IloaderError> {
     let device = {
         let device_guard = device_state.lock().unwrap();
         match &*device_guard {
             Some(d) => d.clone(),
-            None => return Err("No device selected".to_string()),
+            None => return Err(IloaderError::DeviceNotSelected),
         }
     };
     let provider = get_provider(&device).await?;
-    let mut installation_proxy = InstallationProxyClient::connect(&provider)
-        .await
-        .map_err(|e| format!("Failed to connect to installation proxy: {}", e))?;
+    let mut installation_proxy =
+        InstallationProxyClient::connect(&provider)
+            .await
+            .map_err(|e| {
+                IloaderError::InstallationProxy(format!(
+                    "Failed to connect to installation proxy: {}",
+                    e
+                ))
+            })?;
 
     let installed_apps = installation_proxy
         .get_apps(Some("User"), None)
         .await
-        .map_err(|e| format!("Failed to get installed apps: {}", e))?;
+        .map_err(|e| {
+            IloaderError::InstallationProxy(format!("Failed to get installed apps: {}", e))
+        })?;
 
     let mut installed = HashMap::new();
     for (bundle_id, app) in installed_apps {
         let n = app
             .as_dictionary()
             .and_then(|x| x.get("CFBundleDisplayName").and_then(|x| x.as_string()))
-            .ok_or("Failed to parse installed apps".to_string())?;
+            .ok_or_else(|| {
+                IloaderError::InstallationProxy("Failed to parse installed apps".to_string())
+            })?;
 
         if PAIRING_APPS.iter().any(|(name, _)| name == &n) {
             if bundle_id.contains("com.stik.stikdebug") {
@@ -237,25 +313,156 @@ pub async fn installed_pairing_apps(
     Ok(result)
 }
 
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum PairingFormat {
+    Xml,
+    Binary,
+    /// The format expected by one of the apps in `PAIRING_APPS`, by name
+    App(String),
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PairingFileInfo {
+    pub udid: Option<String>,
+    pub host_id: String,
+    pub system_buid: String,
+    pub wifi_mac_address: Option<String>,
+    pub device_certificate_expiry: Option<String>,
+    pub host_certificate_expiry: Option<String>,
+    pub binary: bool,
+}
+
+fn load_pairing_value(bytes: &[u8]) -> Result<plist::Value, IloaderError> {
+    let value: plist::Value = plist::from_bytes(bytes)
+        .map_err(|e| IloaderError::Pairing(format!("Failed to parse pairing file: {}", e)))?;
+    // make sure idevice would actually accept it before we do anything else with it
+    PairingFile::from_value(&value)
+        .map_err(|e| IloaderError::Pairing(format!("Invalid pairing file: {}", e)))?;
+    Ok(value)
+}
+
+fn certificate_expiry(pairing: &plist::Dictionary, key: &str) -> Option<String> {
+    let pem = pairing.get(key)?.as_data()?;
+    let (_, pem) = x509_parser::pem::parse_x509_pem(pem).ok()?;
+    let cert = pem.parse_x509().ok()?;
+    let expiry = cert.validity().not_after.timestamp();
+    chrono::DateTime::from_timestamp(expiry, 0).map(|d| d.to_rfc3339())
+}
+
+pub fn inspect_pairing(bytes: &[u8]) -> Result<PairingFileInfo, IloaderError> {
+    let value = load_pairing_value(bytes)?;
+    let dict = value
+        .as_dictionary()
+        .ok_or_else(|| IloaderError::Pairing("Pairing file is not a dictionary".to_string()))?;
+    let get_string = |key: &str| {
+        dict.get(key)
+            .and_then(|v| v.as_string())
+            .map(|s| s.to_string())
+    };
+
+    Ok(PairingFileInfo {
+        udid: get_string("UDID"),
+        host_id: get_string("HostID")
+            .ok_or_else(|| IloaderError::Pairing("Pairing file is missing HostID".to_string()))?,
+        system_buid: get_string("SystemBUID").ok_or_else(|| {
+            IloaderError::Pairing("Pairing file is missing SystemBUID".to_string())
+        })?,
+        wifi_mac_address: get_string("WiFiMACAddress"),
+        device_certificate_expiry: certificate_expiry(dict, "DeviceCertificate"),
+        host_certificate_expiry: certificate_expiry(dict, "HostCertificate"),
+        binary: bytes.starts_with(b"bplist"),
+    })
+}
+
+impl PairingFormat {
+    pub fn extension(&self) -> Result<&'static str, IloaderError> {
+        match self {
+            PairingFormat::Xml | PairingFormat::Binary => Ok("plist"),
+            PairingFormat::App(app) => PAIRING_APPS
+                .iter()
+                .find(|(name, _)| name == app)
+                .and_then(|(_, path)| path.rsplit_once('.'))
+                .map(|(_, ext)| ext)
+                .ok_or_else(|| IloaderError::Pairing(format!("Unknown pairing app: {}", app))),
+        }
+    }
+}
+
+pub fn convert_pairing(bytes: &[u8], format: &PairingFormat) -> Result<Vec<u8>, IloaderError> {
+    let value = load_pairing_value(bytes)?;
+    let mut out = Vec::new();
+    match format {
+        PairingFormat::Xml => plist::to_writer_xml(&mut out, &value),
+        PairingFormat::Binary => plist::to_writer_binary(&mut out, &value),
+        PairingFormat::App(app) => {
+            // every supported app currently reads the same XML plist, only the file name differs
+            if !PAIRING_APPS.iter().any(|(name, _)| name == app) {
+                return Err(IloaderError::Pairing(format!(
+                    "Unknown pairing app: {}",
+                    app
+                )));
+            }
+            plist::to_writer_xml(&mut out, &value)
+        }
+    }
+    .map_err(|e| IloaderError::Pairing(format!("Failed to serialize pairing file: {}", e)))?;
+    Ok(out)
+}
+
+#[tauri::command]
+pub async fn inspect_pairing_file(path: String) -> Result<PairingFileInfo, IloaderError> {
+    let bytes = tokio::fs::read(&path)
+        .await
+        .map_err(|e| IloaderError::Io(format!("Failed to read pairing file: {}", e)))?;
+    inspect_pairing(&bytes)
+}
+
+#[tauri::command]
+pub async fn convert_pairing_file(
+    input_path: String,
+    output_path: String,
+    format: PairingFormat,
+) -> Result<(), IloaderError> {
+    let bytes = tokio::fs::read(&input_path)
+        .await
+        .map_err(|e| IloaderError::Io(format!("Failed to read pairing file: {}", e)))?;
+    let converted = convert_pairing(&bytes, &format)?;
+    tokio::fs::write(&output_path, converted)
+        .await
+        .map_err(|e| IloaderError::Io(format!("Failed to write pairing file: {}", e)))
+}
+
 pub async fn get_sidestore_info(
     device: DeviceInfo,
     live_container: bool,
-) -> Result<Option<PairingAppInfo>, String> {
+) -> Result<Option<PairingAppInfo>, IloaderError> {
     let provider = get_provider(&device).await?;
-    let mut installation_proxy = InstallationProxyClient::connect(&provider)
-        .await
-        .map_err(|e| format!("Failed to connect to installation proxy: {}", e))?;
+    let mut installation_proxy =
+        InstallationProxyClient::connect(&provider)
+            .await
+            .map_err(|e| {
+                IloaderError::InstallationProxy(format!(
+                    "Failed to connect to installation proxy: {}",
+                    e
+                ))
+            })?;
 
     let installed_apps = installation_proxy
         .get_apps(Some("User"), None)
         .await
-        .map_err(|e| format!("Failed to get installed apps: {}", e))?;
+        .map_err(|e| {
+            IloaderError::InstallationProxy(format!("Failed to get installed apps: {}", e))
+        })?;
 
     for (bundle_id, app) in installed_apps {
         let n = app
             .as_dictionary()
             .and_then(|x| x.get("CFBundleDisplayName").and_then(|x| x.as_string()))
-            .ok_or("Failed to parse installed apps".to_string())?;
+            .ok_or_else(|| {
+                IloaderError::InstallationProxy("Failed to parse installed apps".to_string())
+            })?;
 
         if n == "SideStore" || (live_container && n == "LiveContainer") {
             return Ok(Some(PairingAppInfo {
@@ -272,3 +479,117 @@ pub async fn get_sidestore_info(
 
     Ok(None)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XML_FIXTURE: &[u8] = include_bytes!("../tests/fixtures/pairing.plist");
+    const BINARY_FIXTURE: &[u8] = include_bytes!("../tests/fixtures/pairing-binary.plist");
+
+    fn formats() -> Vec<PairingFormat> {
+        let mut formats = vec![PairingFormat::Xml, PairingFormat::Binary];
+        formats.extend(
+            PAIRING_APPS
+                .iter()
+                .map(|(name, _)| PairingFormat::App(name.to_string())),
+        );
+        formats
+    }
+
+    fn assert_fixture_info(info: &PairingFileInfo) {
+        assert_eq!(info.udid.as_deref(), Some("00008110-001A2B3C4D5E801E"));
+        assert_eq!(info.host_id, "5A1D3C4B-7E8F-4A0B-9C1D-2E3F4A5B6C7D");
+        assert_eq!(info.system_buid, "0E1F2A3B-4C5D-6E7F-8091-A2B3C4D5E6F7");
+        assert_eq!(info.wifi_mac_address.as_deref(), Some("a4:c3:f0:12:34:56"));
+        assert_eq!(
+            info.device_certificate_expiry.as_deref(),
+            Some("2036-06-15T00:00:00+00:00")
+        );
+        assert_eq!(
+            info.host_certificate_expiry.as_deref(),
+            Some("2035-01-01T00:00:00+00:00")
+        );
+    }
+
+    #[test]
+    fn inspects_xml_pairing_file() {
+        let info = inspect_pairing(XML_FIXTURE).unwrap();
+        assert_fixture_info(&info);
+        assert!(!info.binary);
+    }
+
+    #[test]
+    fn inspects_binary_pairing_file() {
+        let info = inspect_pairing(BINARY_FIXTURE).unwrap();
+        assert_fixture_info(&info);
+        assert!(info.binary);
+    }
+
+    #[test]
+    fn rejects_invalid_pairing_file() {
+        assert!(matches!(
+            inspect_pairing(b"not a plist"),
+            Err(IloaderError::Pairing(_))
+        ));
+
+        let mut missing_host_id = plist::from_bytes::<plist::Dictionary>(XML_FIXTURE).unwrap();
+        missing_host_id.remove("HostID");
+        let mut bytes = Vec::new();
+        plist::to_writer_xml(&mut bytes, &missing_host_id).unwrap();
+        assert!(matches!(
+            inspect_pairing(&bytes),
+            Err(IloaderError::Pairing(_))
+        ));
+    }
+
+    #[test]
+    fn round_trips_every_format() {
+        let original: plist::Value = plist::from_bytes(XML_FIXTURE).unwrap();
+        for fixture in [XML_FIXTURE, BINARY_FIXTURE] {
+            for format in formats() {
+                let converted = convert_pairing(fixture, &format).unwrap();
+                let binary = matches!(format, PairingFormat::Binary);
+                assert_eq!(converted.starts_with(b"bplist"), binary, "{:?}", format);
+
+                let value: plist::Value = plist::from_bytes(&converted).unwrap();
+                assert_eq!(value, original, "{:?}", format);
+
+                let info = inspect_pairing(&converted).unwrap();
+                assert_fixture_info(&info);
+
+                // and back again
+                let back = convert_pairing(&converted, &PairingFormat::Binary).unwrap();
+                assert_eq!(plist::from_bytes::<plist::Value>(&back).unwrap(), original);
+            }
+        }
+    }
+
+    #[test]
+    fn app_formats_use_the_apps_extension() {
+        assert_eq!(PairingFormat::Xml.extension().unwrap(), "plist");
+        assert_eq!(PairingFormat::Binary.extension().unwrap(), "plist");
+        assert_eq!(
+            PairingFormat::App("SideStore".to_string())
+                .extension()
+                .unwrap(),
+            "mobiledevicepairing"
+        );
+        assert_eq!(
+            PairingFormat::App("Feather".to_string())
+                .extension()
+                .unwrap(),
+            "plist"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_app() {
+        let format = PairingFormat::App("NotARealApp".to_string());
+        assert!(matches!(
+            convert_pairing(XML_FIXTURE, &format),
+            Err(IloaderError::Pairing(_))
+        ));
+        assert!(matches!(format.extension(), Err(IloaderError::Pairing(_))));
+    }
+}
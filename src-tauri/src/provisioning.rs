@@ -0,0 +1,213 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use idevice::{IdeviceService, misagent::MisagentClient};
+use isideload::dev::provisioning_profiles::ProvisioningProfilesApi;
+use serde::Serialize;
+use tauri::{AppHandle, State};
+
+use crate::{
+    device::{DeviceInfo, DeviceInfoMutex, get_provider},
+    error::IloaderError,
+    sideload::{SideloaderGuard, SideloaderMutex},
+    team::selected_team,
+};
+
+// The interesting parts of a provisioning profile's payload
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileInfo {
+    pub name: Option<String>,
+    pub uuid: Option<String>,
+    pub app_id_name: Option<String>,
+    pub application_identifier: Option<String>,
+    pub team_ids: Vec<String>,
+    pub platforms: Vec<String>,
+    /// unix timestamps (seconds)
+    pub created_at: Option<i64>,
+    pub expires_at: Option<i64>,
+    pub provisioned_devices: Vec<String>,
+    pub entitlements: Option<plist::Dictionary>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamProfile {
+    pub profile_id: Option<String>,
+    pub name: Option<String>,
+    pub profile: Option<ProfileInfo>,
+    pub error: Option<String>,
+}
+
+fn unix_time(date: &plist::Date) -> Option<i64> {
+    SystemTime::from(*date)
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs() as i64)
+}
+
+fn strings(value: Option<&plist::Value>) -> Vec<String> {
+    value
+        .and_then(|v| v.as_array())
+        .map(|a| {
+            a.iter()
+                .filter_map(|v| v.as_string().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// A .mobileprovision is a CMS signed message wrapping an XML plist. The signature isn't needed
+// to show what's inside, so pull the plist straight out of the envelope.
+pub fn decode_profile(bytes: &[u8]) -> Result<ProfileInfo, IloaderError> {
+    let start = bytes
+        .windows(5)
+        .position(|w| w == b"<?xml")
+        .ok_or_else(|| IloaderError::Other("Provisioning profile has no payload".to_string()))?;
+    let end_tag = b"</plist>";
+    let end = bytes[start..]
+        .windows(end_tag.len())
+        .position(|w| w == end_tag)
+        .map(|p| start + p + end_tag.len())
+        .ok_or_else(|| IloaderError::Other("Provisioning profile is truncated".to_string()))?;
+
+    let payload: plist::Dictionary = plist::from_bytes(&bytes[start..end])
+        .map_err(|e| IloaderError::Other(format!("Invalid provisioning profile: {}", e)))?;
+    let string = |key: &str| {
+        payload
+            .get(key)
+            .and_then(|v| v.as_string())
+            .map(|s| s.to_string())
+    };
+    let date = |key: &str| {
+        payload
+            .get(key)
+            .and_then(|v| v.as_date())
+            .and_then(|d| unix_time(&d))
+    };
+    let entitlements = payload
+        .get("Entitlements")
+        .and_then(|v| v.as_dictionary())
+        .cloned();
+
+    Ok(ProfileInfo {
+        name: string("Name"),
+        uuid: string("UUID"),
+        app_id_name: string("AppIDName"),
+        application_identifier: entitlements
+            .as_ref()
+            .and_then(|e| e.get("application-identifier"))
+            .and_then(|v| v.as_string())
+            .map(|s| s.to_string()),
+        team_ids: strings(payload.get("TeamIdentifier")),
+        platforms: strings(payload.get("Platform")),
+        created_at: date("CreationDate"),
+        expires_at: date("ExpirationDate"),
+        provisioned_devices: strings(payload.get("ProvisionedDevices")),
+        entitlements,
+    })
+}
+
+#[tauri::command]
+pub async fn list_provisioning_profiles(
+    handle: AppHandle,
+    sideloader_state: State<'_, SideloaderMutex>,
+    email: Option<String>,
+) -> Result<Vec<TeamProfile>, IloaderError> {
+    let mut sideloader = SideloaderGuard::take(&sideloader_state, email.as_deref())?;
+
+    let team = selected_team(&handle, &mut sideloader).await?;
+    let profiles = sideloader
+        .get_mut()
+        .get_dev_session()
+        .list_provisioning_profiles(&team, None)
+        .await
+        .map_err(|e| {
+            IloaderError::developer(format!("Failed to list provisioning profiles: {:?}.", e))
+        })?;
+
+    Ok(profiles
+        .into_iter()
+        .map(|p| {
+            let decoded = p.encoded_profile.as_deref().map(decode_profile);
+            TeamProfile {
+                profile_id: p.provisioning_profile_id,
+                name: p.name,
+                error: decoded
+                    .as_ref()
+                    .and_then(|d| d.as_ref().err())
+                    .map(|e| e.to_string()),
+                profile: decoded.and_then(|d| d.ok()),
+            }
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub async fn download_provisioning_profile(
+    handle: AppHandle,
+    sideloader_state: State<'_, SideloaderMutex>,
+    profile_id: String,
+    path: String,
+    email: Option<String>,
+) -> Result<(), IloaderError> {
+    let mut sideloader = SideloaderGuard::take(&sideloader_state, email.as_deref())?;
+
+    let team = selected_team(&handle, &mut sideloader).await?;
+    let profiles = sideloader
+        .get_mut()
+        .get_dev_session()
+        .list_provisioning_profiles(&team, None)
+        .await
+        .map_err(|e| {
+            IloaderError::developer(format!("Failed to list provisioning profiles: {:?}.", e))
+        })?;
+
+    let encoded = profiles
+        .into_iter()
+        .find(|p| p.provisioning_profile_id.as_deref() == Some(profile_id.as_str()))
+        .and_then(|p| p.encoded_profile)
+        .ok_or_else(|| {
+            IloaderError::DeveloperApi(format!("Provisioning profile {} not found", profile_id))
+        })?;
+
+    std::fs::write(&path, encoded)
+        .map_err(|e| IloaderError::Io(format!("Failed to write {}: {}", path, e)))
+}
+
+#[tauri::command]
+pub async fn inspect_provisioning_profile(path: String) -> Result<ProfileInfo, IloaderError> {
+    let bytes = std::fs::read(&path)
+        .map_err(|e| IloaderError::Io(format!("Failed to read {}: {}", path, e)))?;
+    decode_profile(&bytes)
+}
+
+// Profiles installed on a device, as reported by misagent. Profiles that fail to decode are left
+// out since there's nothing useful to show for them.
+pub async fn installed_profiles(device: &DeviceInfo) -> Result<Vec<ProfileInfo>, IloaderError> {
+    let provider = get_provider(device).await?;
+
+    let mut misagent = MisagentClient::connect(&provider)
+        .await
+        .map_err(|e| IloaderError::Lockdown(format!("Failed to connect to misagent: {}", e)))?;
+    let profiles = misagent
+        .copy_all(false)
+        .await
+        .map_err(|e| IloaderError::Lockdown(format!("Failed to list profiles: {}", e)))?;
+
+    Ok(profiles
+        .iter()
+        .filter_map(|p| decode_profile(p).ok())
+        .collect())
+}
+
+#[tauri::command]
+pub async fn device_provisioning_profiles(
+    device_state: State<'_, DeviceInfoMutex>,
+) -> Result<Vec<ProfileInfo>, IloaderError> {
+    let device = device_state
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(IloaderError::DeviceNotSelected)?;
+    installed_profiles(&device).await
+}
@@ -0,0 +1,199 @@
+use tauri::{AppHandle, State, Window};
+
+use crate::{
+    device::DeviceInfoMutex,
+    error::IloaderError,
+    history::{InstallKind, InstallRecord, installed_on},
+    ipa_cache::cached_ipa,
+    operation::Operation,
+    sideload::{SideloaderGuard, SideloaderMutex, sideload},
+    team::selected_team,
+};
+
+// isideload appends the team ID to the bundle ID of apps installed by free accounts
+fn team_id_suffix(installed_bundle_id: &str) -> Option<&str> {
+    let (_, suffix) = installed_bundle_id.rsplit_once('.')?;
+    (suffix.len() == 10
+        && suffix
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()))
+    .then_some(suffix)
+}
+
+// The team an installed app was signed by, going by iloader's own install history first
+fn app_team_id(records: &[InstallRecord], installed_bundle_id: &str) -> Option<String> {
+    records
+        .iter()
+        .find_map(|record| {
+            let original = record.bundle_id.as_deref()?;
+            let team_id = record.team_id.as_deref()?;
+            (installed_bundle_id == original
+                || installed_bundle_id == format!("{}.{}", original, team_id))
+            .then(|| team_id.to_string())
+        })
+        .or_else(|| team_id_suffix(installed_bundle_id).map(|t| t.to_string()))
+}
+
+async fn refresh_app(
+    handle: &AppHandle,
+    device_state: &DeviceInfoMutex,
+    sideloader_state: &SideloaderMutex,
+    bundle_id: &str,
+    team_id: &str,
+    signing_team_id: &str,
+    email: Option<String>,
+) -> Result<(), IloaderError> {
+    // re-signing with another team would install a second copy next to the app
+    if team_id != signing_team_id {
+        return Err(IloaderError::Other(format!(
+            "{} was signed by team {}, log in with that team to refresh it",
+            bundle_id, team_id
+        )));
+    }
+    let ipa = cached_ipa(handle, bundle_id, team_id).ok_or_else(|| {
+        IloaderError::Other(format!(
+            "No cached IPA of {} to re-sign, install it again from its IPA",
+            bundle_id
+        ))
+    })?;
+    // installing over the existing app upgrades it in place, so its data is kept
+    sideload(
+        handle,
+        device_state,
+        sideloader_state,
+        ipa.to_string_lossy().to_string(),
+        InstallKind::Refresh,
+        None,
+        email,
+    )
+    .await
+    .map(|_| ())
+}
+
+async fn team_id(
+    handle: &AppHandle,
+    sideloader_state: &SideloaderMutex,
+    email: Option<&str>,
+) -> Result<String, IloaderError> {
+    let mut sideloader = SideloaderGuard::take(sideloader_state, email)?;
+    Ok(selected_team(handle, &mut sideloader).await?.team_id)
+}
+
+// Re-sign and reinstall apps from their cached IPAs, one step per bundle ID. Each app is matched
+// to its cached IPA by the team it was signed with. A failure is reported on that app's step and
+// the remaining apps are still refreshed.
+#[tauri::command]
+pub async fn refresh_apps_operation(
+    handle: AppHandle,
+    window: Window,
+    device_state: State<'_, DeviceInfoMutex>,
+    sideloader_state: State<'_, SideloaderMutex>,
+    bundle_ids: Vec<String>,
+    email: Option<String>,
+) -> Result<(), IloaderError> {
+    let op = Operation::new("refresh_apps".to_string(), &window);
+    let Some(first) = bundle_ids.first() else {
+        return Ok(());
+    };
+
+    op.start(first)?;
+    let signing_team_id = op.fail_if_err(
+        first,
+        team_id(&handle, &sideloader_state, email.as_deref()).await,
+    )?;
+    let records = device_state
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|device| installed_on(&handle, &device.uuid))
+        .unwrap_or_default();
+
+    let mut first_error = None;
+    for (i, bundle_id) in bundle_ids.iter().enumerate() {
+        if i > 0 {
+            op.start(bundle_id)?;
+        }
+        // apps installed with a paid account keep their bundle ID and were most likely signed
+        // by the team that's selected now
+        let team_id = app_team_id(&records, bundle_id).unwrap_or_else(|| signing_team_id.clone());
+        match refresh_app(
+            &handle,
+            &device_state,
+            &sideloader_state,
+            bundle_id,
+            &team_id,
+            &signing_team_id,
+            email.clone(),
+        )
+        .await
+        {
+            Ok(()) => op.complete(bundle_id)?,
+            Err(e) => {
+                let _ = op.fail::<()>(bundle_id, e.clone());
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(bundle_id: &str, team_id: &str) -> InstallRecord {
+        InstallRecord {
+            id: 1,
+            kind: InstallKind::Sideload,
+            device_udid: "00008030-0000000000000000".to_string(),
+            device_name: "iPhone".to_string(),
+            email: "jane@example.com".to_string(),
+            team_id: Some(team_id.to_string()),
+            source: "/tmp/app.ipa".to_string(),
+            source_hash: None,
+            bundle_id: Some(bundle_id.to_string()),
+            success: true,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn team_comes_from_the_install_record() {
+        let records = vec![
+            record("com.example.app", "ABCDE12345"),
+            record("com.example.paid", "VWXYZ67890"),
+        ];
+        assert_eq!(
+            app_team_id(&records, "com.example.app.ABCDE12345").as_deref(),
+            Some("ABCDE12345")
+        );
+        assert_eq!(
+            app_team_id(&records, "com.example.paid").as_deref(),
+            Some("VWXYZ67890")
+        );
+    }
+
+    #[test]
+    fn team_falls_back_to_the_bundle_id_suffix() {
+        assert_eq!(
+            app_team_id(&[], "com.example.app.QWERT12345").as_deref(),
+            Some("QWERT12345")
+        );
+        assert_eq!(app_team_id(&[], "com.example.app"), None);
+        assert_eq!(app_team_id(&[], "com.example.lowercase1"), None);
+        assert_eq!(app_team_id(&[], "com.example.SHORT1"), None);
+    }
+
+    #[test]
+    fn records_for_other_apps_are_ignored() {
+        let records = vec![record("com.example.app", "ABCDE12345")];
+        assert_eq!(
+            app_team_id(&records, "com.example.app.QWERT12345").as_deref(),
+            Some("QWERT12345")
+        );
+    }
+}
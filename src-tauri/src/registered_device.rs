@@ -0,0 +1,120 @@
+use isideload::dev::devices::{DeveloperDevice, DevicesApi};
+use serde::Serialize;
+use tauri::{AppHandle, State};
+
+use crate::{
+    device::DeviceInfoMutex,
+    error::IloaderError,
+    sideload::{SideloaderGuard, SideloaderMutex},
+    team::selected_team,
+};
+
+// Devices registered on the developer account, each taking one of the account's device slots.
+// The Xcode developer services iloader talks to can list and add devices but offer no way to
+// disable or rename them; that needs the developer website or App Store Connect.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisteredDeviceInfo {
+    pub device_id: Option<String>,
+    pub name: Option<String>,
+    pub udid: Option<String>,
+    pub platform: Option<String>,
+    pub status: Option<String>,
+    /// Whether this is the device currently selected in iloader
+    pub selected: bool,
+}
+
+impl RegisteredDeviceInfo {
+    fn new(device: &DeveloperDevice, selected_udid: Option<&str>) -> Self {
+        RegisteredDeviceInfo {
+            device_id: device.device_id.clone(),
+            name: device.name.clone(),
+            udid: device.device_number.clone(),
+            platform: device.device_platform.clone(),
+            status: device.status.clone(),
+            selected: selected_udid.is_some_and(|udid| {
+                device
+                    .device_number
+                    .as_deref()
+                    .is_some_and(|n| n.eq_ignore_ascii_case(udid))
+            }),
+        }
+    }
+}
+
+fn selected_udid(device_state: &State<'_, DeviceInfoMutex>) -> Option<String> {
+    device_state
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|d| d.uuid.clone())
+}
+
+#[tauri::command]
+pub async fn list_registered_devices(
+    handle: AppHandle,
+    sideloader_state: State<'_, SideloaderMutex>,
+    device_state: State<'_, DeviceInfoMutex>,
+    email: Option<String>,
+) -> Result<Vec<RegisteredDeviceInfo>, IloaderError> {
+    let selected = selected_udid(&device_state);
+    let mut sideloader = SideloaderGuard::take(&sideloader_state, email.as_deref())?;
+
+    let team = selected_team(&handle, &mut sideloader).await?;
+    let devices = sideloader
+        .get_mut()
+        .get_dev_session()
+        .list_devices(&team, None)
+        .await
+        .map_err(|e| IloaderError::developer(format!("Failed to list devices: {:?}.", e)))?;
+
+    Ok(devices
+        .iter()
+        .map(|d| RegisteredDeviceInfo::new(d, selected.as_deref()))
+        .collect())
+}
+
+// Register the selected device on the account ahead of time, which sideloading otherwise does
+// on its first install
+#[tauri::command]
+pub async fn register_device(
+    handle: AppHandle,
+    sideloader_state: State<'_, SideloaderMutex>,
+    device_state: State<'_, DeviceInfoMutex>,
+    name: Option<String>,
+    email: Option<String>,
+) -> Result<RegisteredDeviceInfo, IloaderError> {
+    let device = device_state
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(IloaderError::DeviceNotSelected)?;
+    let mut sideloader = SideloaderGuard::take(&sideloader_state, email.as_deref())?;
+
+    let team = selected_team(&handle, &mut sideloader).await?;
+    let dev_session = sideloader.get_mut().get_dev_session();
+
+    let existing = dev_session
+        .list_devices(&team, None)
+        .await
+        .map_err(|e| IloaderError::developer(format!("Failed to list devices: {:?}.", e)))?;
+    if let Some(registered) = existing.iter().find(|d| {
+        d.device_number
+            .as_deref()
+            .is_some_and(|n| n.eq_ignore_ascii_case(&device.uuid))
+    }) {
+        return Ok(RegisteredDeviceInfo::new(registered, Some(&device.uuid)));
+    }
+
+    let registered = dev_session
+        .add_device(
+            &team,
+            name.as_deref().unwrap_or(&device.name),
+            &device.uuid,
+            None,
+        )
+        .await
+        .map_err(|e| IloaderError::developer(format!("Failed to register device: {:?}.", e)))?;
+
+    Ok(RegisteredDeviceInfo::new(&registered, Some(&device.uuid)))
+}
@@ -0,0 +1,127 @@
+use isideload::{
+    anisette::remote_v3::RemoteV3AnisetteProvider,
+    auth::apple_account::AppleAccount,
+    dev::{developer_session::DeveloperSession, teams::TeamsApi},
+};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tracing::{debug, warn};
+
+use crate::{
+    error::IloaderError,
+    storage::{SESSION_KEY, storage},
+};
+
+const XCODE_APP: &str = "com.apple.gs.xcode.auth";
+
+#[derive(Serialize, Deserialize)]
+struct CachedSession {
+    adsid: String,
+    token: String,
+    // unix timestamp (seconds) after which the token is no longer accepted by Apple
+    expires_at: i64,
+}
+
+pub async fn cache_session(
+    app: &AppHandle,
+    email: &str,
+    account: &mut AppleAccount,
+) -> Result<(), IloaderError> {
+    let app_token = account
+        .get_app_token(XCODE_APP)
+        .await
+        .map_err(|e| IloaderError::Auth(format!("Failed to get session token: {}", e)))?;
+
+    let session = CachedSession {
+        adsid: account
+            .get_adsid()
+            .map_err(|e| IloaderError::Auth(format!("Failed to get account ID: {}", e)))?
+            .to_string(),
+        token: app_token.token,
+        expires_at: chrono::Utc::now().timestamp() + app_token.duration as i64,
+    };
+
+    storage(app, email)?
+        .store(
+            SESSION_KEY,
+            &serde_json::to_string(&session).map_err(|e| {
+                IloaderError::Storage(format!("Failed to serialize session: {}", e))
+            })?,
+        )
+        .map_err(|e| IloaderError::Storage(format!("Failed to save session: {}", e)))?;
+
+    debug!("Cached session for {}", email);
+    Ok(())
+}
+
+pub fn clear_session(app: &AppHandle, email: &str) {
+    let result = storage(app, email).and_then(|storage| {
+        storage
+            .delete(SESSION_KEY)
+            .map_err(|e| IloaderError::Storage(e.to_string()))
+    });
+    if let Err(e) = result {
+        warn!("Failed to delete cached session: {}", e);
+    }
+}
+
+fn load_session(app: &AppHandle, email: &str) -> Option<CachedSession> {
+    let stored = match storage(app, email).and_then(|storage| {
+        storage
+            .retrieve(SESSION_KEY)
+            .map_err(|e| IloaderError::Storage(e.to_string()))
+    }) {
+        Ok(stored) => stored?,
+        Err(e) => {
+            warn!("Failed to read cached session: {}", e);
+            return None;
+        }
+    };
+
+    let session = match serde_json::from_str::<CachedSession>(&stored) {
+        Ok(session) => session,
+        Err(e) => {
+            warn!("Discarding unreadable cached session: {}", e);
+            clear_session(app, email);
+            return None;
+        }
+    };
+
+    if session.expires_at <= chrono::Utc::now().timestamp() {
+        debug!("Cached session for {} has expired", email);
+        clear_session(app, email);
+        return None;
+    }
+
+    Some(session)
+}
+
+// Rebuild a developer session from the cached token, making sure Apple still accepts it.
+// Returns None when there is no usable session and a full login is required.
+pub async fn restore_session(
+    app: &AppHandle,
+    email: &str,
+    anisette_provider: RemoteV3AnisetteProvider,
+) -> Option<DeveloperSession> {
+    let session = load_session(app, email)?;
+
+    let mut dev_session =
+        match DeveloperSession::from_token(session.adsid, session.token, anisette_provider).await {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("Failed to restore cached session: {}", e);
+                return None;
+            }
+        };
+
+    if let Err(e) = dev_session.list_teams().await {
+        debug!(
+            "Cached session was rejected, falling back to login: {:?}",
+            e
+        );
+        clear_session(app, email);
+        return None;
+    }
+
+    Some(dev_session)
+}
@@ -1,30 +1,103 @@
-use std::{path::PathBuf, sync::Mutex};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
 
 use crate::{
     device::{get_provider, DeviceInfoMutex},
+    error::IloaderError,
+    history::{InstallKind, InstallRecord, file_hash, next_record_id, record_install},
+    ipa_cache::{cache_ipa, ipa_bundle_id},
     operation::Operation,
     pairing::{get_sidestore_info, place_pairing},
 };
 use isideload::sideload::{application::SpecialApp, sideloader::Sideloader};
 use tauri::{AppHandle, Manager, State, Window};
+use tracing::warn;
+
+#[derive(Default)]
+pub struct Sideloaders {
+    accounts: HashMap<String, Sideloader>,
+    active: Option<String>,
+}
+
+impl Sideloaders {
+    pub fn insert(&mut self, email: String, sideloader: Sideloader) {
+        self.accounts.insert(email.clone(), sideloader);
+        self.active = Some(email);
+    }
+
+    // Add or swap in an account's sideloader without changing which account is active
+    pub fn replace(&mut self, email: String, sideloader: Sideloader) {
+        self.accounts.insert(email, sideloader);
+    }
+
+    pub fn remove(&mut self, email: &str) -> Option<Sideloader> {
+        let removed = self.accounts.remove(email);
+        // don't silently retarget commands at whichever account happens to be left
+        if self.active.as_deref() == Some(email) {
+            self.active = None;
+        }
+        removed
+    }
+
+    pub fn active(&self) -> Option<&str> {
+        self.active.as_deref()
+    }
 
-pub type SideloaderMutex = Mutex<Option<Sideloader>>;
+    pub fn set_active(&mut self, email: &str) -> Result<(), IloaderError> {
+        if !self.accounts.contains_key(email) {
+            return Err(IloaderError::NotLoggedIn(format!(
+                "Not logged in as {}",
+                email
+            )));
+        }
+        self.active = Some(email.to_string());
+        Ok(())
+    }
+
+    pub fn emails(&self) -> Vec<String> {
+        let mut emails: Vec<String> = self.accounts.keys().cloned().collect();
+        emails.sort();
+        emails
+    }
+
+    pub fn resolve(&self, email: Option<&str>) -> Result<String, IloaderError> {
+        email
+            .map(|e| e.to_lowercase())
+            .or_else(|| self.active.clone())
+            .ok_or_else(|| IloaderError::NotLoggedIn("Not logged in".to_string()))
+    }
+}
+
+pub type SideloaderMutex = Mutex<Sideloaders>;
 
 pub struct SideloaderGuard<'a> {
     state: &'a SideloaderMutex,
+    email: String,
     sideloader: Option<Sideloader>,
 }
 
 impl<'a> SideloaderGuard<'a> {
-    pub fn take(state: &'a SideloaderMutex) -> Result<Self, String> {
+    pub fn take(state: &'a SideloaderMutex, email: Option<&str>) -> Result<Self, IloaderError> {
         let mut guard = state.lock().unwrap();
-        let sideloader = guard.take().ok_or_else(|| "Not logged in".to_string())?;
+        let email = guard.resolve(email)?;
+        let sideloader = guard
+            .accounts
+            .remove(&email)
+            .ok_or_else(|| IloaderError::NotLoggedIn(format!("Not logged in as {}", email)))?;
         Ok(Self {
             state,
+            email,
             sideloader: Some(sideloader),
         })
     }
 
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+
     pub fn get_mut(&mut self) -> &mut Sideloader {
         self.sideloader
             .as_mut()
@@ -35,46 +108,99 @@ impl<'a> SideloaderGuard<'a> {
 impl Drop for SideloaderGuard<'_> {
     fn drop(&mut self) {
         let mut guard = self.state.lock().unwrap();
-        *guard = self.sideloader.take();
+        if let Some(sideloader) = self.sideloader.take() {
+            guard.accounts.insert(self.email.clone(), sideloader);
+        }
     }
 }
 
+// `source` is where the IPA came from when it isn't `app_path` itself, e.g. a download URL
 pub async fn sideload(
-    device_state: State<'_, DeviceInfoMutex>,
-    sideloader_state: State<'_, SideloaderMutex>,
+    handle: &AppHandle,
+    device_state: &DeviceInfoMutex,
+    sideloader_state: &SideloaderMutex,
     app_path: String,
-) -> Result<Option<SpecialApp>, String> {
+    kind: InstallKind,
+    source: Option<String>,
+    email: Option<String>,
+) -> Result<Option<SpecialApp>, IloaderError> {
     let device = {
         let device_lock = device_state.lock().unwrap();
         match &*device_lock {
             Some(d) => d.clone(),
-            None => return Err("No device selected".to_string()),
+            None => return Err(IloaderError::DeviceNotSelected),
         }
     };
 
     let provider = get_provider(&device).await?;
 
-    let mut sideloader = SideloaderGuard::take(&sideloader_state)?;
+    let mut sideloader = SideloaderGuard::take(sideloader_state, email.as_deref())?;
 
-    sideloader
+    let result = sideloader
         .get_mut()
-        .install_app(&provider, app_path.into(), false)
+        .install_app(&provider, app_path.clone().into(), false)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(|e| IloaderError::sideload(e.to_string()));
+
+    // successful installs keep a copy of the IPA so the app can be refreshed later
+    let ipa = Path::new(&app_path);
+    let bundle_id = match &result {
+        Ok(_) => cache_ipa(handle, ipa)
+            .inspect_err(|e| warn!("Failed to cache {}: {}", app_path, e))
+            .ok(),
+        Err(_) => ipa_bundle_id(ipa).ok(),
+    };
+    // the team install_app signed with, which is only saved when the account had to pick one
+    let team_id = sideloader
+        .get_mut()
+        .get_team()
+        .await
+        .inspect_err(|e| warn!("Failed to get the team used for {}: {}", app_path, e))
+        .ok()
+        .map(|team| team.team_id);
+    record_install(
+        handle,
+        InstallRecord {
+            id: next_record_id(),
+            kind,
+            device_udid: device.uuid,
+            device_name: device.name,
+            email: sideloader.email().to_string(),
+            team_id,
+            source: source.unwrap_or_else(|| app_path.clone()),
+            source_hash: file_hash(ipa),
+            bundle_id,
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        },
+    );
+
+    result
 }
 
 #[tauri::command]
 pub async fn sideload_operation(
+    handle: AppHandle,
     window: Window,
     device_state: State<'_, DeviceInfoMutex>,
     sideloader_state: State<'_, SideloaderMutex>,
     app_path: String,
-) -> Result<(), String> {
+    email: Option<String>,
+) -> Result<(), IloaderError> {
     let op = Operation::new("sideload".to_string(), &window);
     op.start("install")?;
     op.fail_if_err(
         "install",
-        sideload(device_state, sideloader_state, app_path).await,
+        sideload(
+            &handle,
+            &device_state,
+            &sideloader_state,
+            app_path,
+            InstallKind::Sideload,
+            None,
+            email,
+        )
+        .await,
     )?;
     op.complete("install")?;
     Ok(())
@@ -88,7 +214,8 @@ pub async fn install_sidestore_operation(
     sideloader_state: State<'_, SideloaderMutex>,
     nightly: bool,
     live_container: bool,
-) -> Result<(), String> {
+    email: Option<String>,
+) -> Result<(), IloaderError> {
     let op = Operation::new("install_sidestore".to_string(), &window);
     op.start("download")?;
     // TODO: Cache & check version to avoid re-downloading
@@ -113,7 +240,7 @@ pub async fn install_sidestore_operation(
     let dest = handle
         .path()
         .temp_dir()
-        .map_err(|e| format!("Failed to get temp dir: {:?}", e))?
+        .map_err(|e| IloaderError::Io(format!("Failed to get temp dir: {:?}", e)))?
         .join(filename);
     op.fail_if_err("download", download(url, &dest).await)?;
     op.move_on("download", "install")?;
@@ -121,15 +248,19 @@ pub async fn install_sidestore_operation(
         let device_guard = device_state.lock().unwrap();
         match &*device_guard {
             Some(d) => d.clone(),
-            None => return op.fail("install", "No device selected".to_string()),
+            None => return op.fail("install", IloaderError::DeviceNotSelected),
         }
     };
     op.fail_if_err(
         "install",
         sideload(
-            device_state,
-            sideloader_state,
+            &handle,
+            &device_state,
+            &sideloader_state,
             dest.to_string_lossy().to_string(),
+            InstallKind::SideStore,
+            Some(url.to_string()),
+            email,
         )
         .await,
     )?;
@@ -146,7 +277,7 @@ pub async fn install_sidestore_operation(
     } else {
         return op.fail(
             "pairing",
-            "Could not find SideStore's bundle ID".to_string(),
+            IloaderError::InstallationProxy("Could not find SideStore's bundle ID".to_string()),
         );
     }
 
@@ -154,21 +285,24 @@ pub async fn install_sidestore_operation(
     Ok(())
 }
 
-pub async fn download(url: impl AsRef<str>, dest: &PathBuf) -> Result<(), String> {
+pub async fn download(url: impl AsRef<str>, dest: &PathBuf) -> Result<(), IloaderError> {
     let response = reqwest::get(url.as_ref())
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| IloaderError::Network(e.to_string()))?;
     if !response.status().is_success() {
-        return Err(format!(
+        return Err(IloaderError::Network(format!(
             "Failed to download file: HTTP {}",
             response.status()
-        ));
+        )));
     }
 
-    let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|e| IloaderError::Network(e.to_string()))?;
     tokio::fs::write(dest, &bytes)
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| IloaderError::Io(e.to_string()))?;
 
     Ok(())
 }
@@ -0,0 +1,661 @@
+use isideload::util::{
+    fs_storage::FsStorage, keyring_storage::KeyringStorage, storage::SideloadingStorage,
+};
+use keyring::Entry;
+use rootcause::{Report, report};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::HashSet, path::PathBuf, sync::Mutex};
+use tauri::{AppHandle, Manager, State};
+use tracing::{debug, warn};
+
+use crate::{
+    encrypted_storage::EncryptedFsStorage, error::IloaderError, sideload::SideloaderMutex,
+};
+
+const SERVICE: &str = "iloader";
+const ANISETTE_STATE_KEY: &str = "anisette_state";
+pub const SESSION_KEY: &str = "session";
+// only used by the encrypted backend, the keyring keeps passwords under (SERVICE, email)
+const PASSWORD_KEY: &str = "password";
+
+// Keys iloader itself knows about; isideload may store more (e.g. certificates) in the same namespace
+const KNOWN_KEYS: &[(&str, &str)] = &[
+    (ANISETTE_STATE_KEY, "Anisette provisioning data"),
+    (SESSION_KEY, "Cached developer session"),
+];
+
+static KEYRING_AVAILABLE: Mutex<Option<bool>> = Mutex::new(None);
+// Accounts whose shared entries have already been copied over during this run
+static MIGRATED_ACCOUNTS: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum StorageBackend {
+    Keyring,
+    EncryptedFile,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredEntry {
+    pub key: String,
+    pub description: Option<String>,
+    pub backend: StorageBackend,
+}
+
+fn account_namespace(email: &str) -> String {
+    format!("{}:{}", SERVICE, email.to_lowercase())
+}
+
+fn data_dir(app: &AppHandle) -> PathBuf {
+    app.path()
+        .app_data_dir()
+        .unwrap_or_else(|_| PathBuf::from("Failed to get app data directory"))
+}
+
+fn account_dir(app: &AppHandle, email: &str) -> PathBuf {
+    data_dir(app).join("accounts").join(email.to_lowercase())
+}
+
+pub fn active_backend() -> StorageBackend {
+    if keyring_available() {
+        StorageBackend::Keyring
+    } else {
+        StorageBackend::EncryptedFile
+    }
+}
+
+fn encrypted_storage(app: &AppHandle, email: &str) -> Result<EncryptedFsStorage, IloaderError> {
+    EncryptedFsStorage::new(account_dir(app, email), &data_dir(app))
+}
+
+fn backend_storage(
+    app: &AppHandle,
+    email: &str,
+    backend: StorageBackend,
+) -> Result<Box<dyn SideloadingStorage>, IloaderError> {
+    Ok(match backend {
+        StorageBackend::Keyring => Box::new(KeyringStorage::new(account_namespace(email))),
+        StorageBackend::EncryptedFile => Box::new(encrypted_storage(app, email)?),
+    })
+}
+
+// Anisette and sideloader data for one Apple ID. Every account gets its own keyring service or
+// data directory so provisioning data and certificates are never shared between accounts.
+pub fn storage(app: &AppHandle, email: &str) -> Result<Box<dyn SideloadingStorage>, IloaderError> {
+    let storage = backend_storage(app, email, active_backend())?;
+
+    let first_use = MIGRATED_ACCOUNTS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashSet::new)
+        .insert(email.to_lowercase());
+    if first_use {
+        migrate_shared_entries(app, email, storage.as_ref());
+    }
+
+    Ok(storage)
+}
+
+// The pre-namespacing location that every account used to share
+fn shared_storage(app: &AppHandle) -> Box<dyn SideloadingStorage> {
+    if keyring_available() {
+        Box::new(KeyringStorage::new(SERVICE.to_string()))
+    } else {
+        Box::new(LegacyFiles(data_dir(app)))
+    }
+}
+
+// Plaintext files written by isideload's FsStorage. Its delete only empties a file, which would
+// leave a readable (and, for the signing key, still present) copy behind, so this removes it.
+struct LegacyFiles(PathBuf);
+
+impl LegacyFiles {
+    fn files(&self) -> FsStorage {
+        FsStorage::new(self.0.clone())
+    }
+}
+
+impl SideloadingStorage for LegacyFiles {
+    fn store(&self, key: &str, value: &str) -> Result<(), Report> {
+        self.files().store(key, value)
+    }
+
+    fn retrieve(&self, key: &str) -> Result<Option<String>, Report> {
+        self.files().retrieve(key)
+    }
+
+    fn store_data(&self, key: &str, value: &[u8]) -> Result<(), Report> {
+        self.files().store_data(key, value)
+    }
+
+    fn retrieve_data(&self, key: &str) -> Result<Option<Vec<u8>>, Report> {
+        self.files().retrieve_data(key)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Report> {
+        match std::fs::remove_file(self.0.join(key)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(report!("{}", e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+// isideload keeps the account's RSA signing key (PKCS#8 DER) under the SHA-256 of its email
+pub fn signing_key_key(email: &str) -> String {
+    format!("{:x}/key", Sha256::digest(email.to_lowercase().as_bytes()))
+}
+
+// Reads one entry, treating the empty value isideload's default delete leaves as missing. Binary
+// entries go through the *_data methods, which some backends store differently.
+fn read_entry(
+    storage: &dyn SideloadingStorage,
+    key: &str,
+    binary: bool,
+) -> Result<Option<Vec<u8>>, Report> {
+    let value = if binary {
+        storage.retrieve_data(key)?
+    } else {
+        storage.retrieve(key)?.map(String::into_bytes)
+    };
+    Ok(value.filter(|v| !v.is_empty()))
+}
+
+fn write_entry(
+    storage: &dyn SideloadingStorage,
+    key: &str,
+    value: &[u8],
+    binary: bool,
+) -> Result<(), Report> {
+    if binary {
+        storage.store_data(key, value)
+    } else {
+        storage.store(key, &String::from_utf8_lossy(value))
+    }
+}
+
+// Move what older versions kept in the shared location into the account's own namespace, so
+// upgrading doesn't cost a fresh anisette provisioning, a new login or a new certificate. Entries
+// the account already has win. Everything is removed once copied: the session and signing key
+// only ever belonged to this account, and the anisette state goes to the first account that asks
+// for it so accounts stop sharing one provisioned identity; the others provision their own.
+fn copy_legacy_entries(
+    email: &str,
+    legacy: &dyn SideloadingStorage,
+    storage: &dyn SideloadingStorage,
+) {
+    let legacy_session_key = format!("session_{}", email.to_lowercase());
+    let signing_key = signing_key_key(email);
+
+    for (legacy_key, key, binary) in [
+        (ANISETTE_STATE_KEY, ANISETTE_STATE_KEY, false),
+        (legacy_session_key.as_str(), SESSION_KEY, false),
+        (signing_key.as_str(), signing_key.as_str(), true),
+    ] {
+        if matches!(read_entry(storage, key, binary), Ok(Some(_))) {
+            continue;
+        }
+        let value = match read_entry(legacy, legacy_key, binary) {
+            Ok(Some(value)) => value,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!("Failed to read shared {}: {}", legacy_key, e);
+                continue;
+            }
+        };
+        if let Err(e) = write_entry(storage, key, &value, binary) {
+            warn!("Failed to copy shared {} for {}: {}", legacy_key, email, e);
+            continue;
+        }
+        if let Err(e) = legacy.delete(legacy_key) {
+            warn!("Failed to remove shared {}: {}", legacy_key, e);
+        }
+        debug!("Moved shared {} into the storage of {}", legacy_key, email);
+    }
+}
+
+fn migrate_shared_entries(app: &AppHandle, email: &str, storage: &dyn SideloadingStorage) {
+    copy_legacy_entries(email, shared_storage(app).as_ref(), storage);
+}
+
+fn password_entry(email: &str) -> Result<Entry, IloaderError> {
+    Entry::new(SERVICE, email).map_err(|e| {
+        IloaderError::Storage(format!(
+            "Failed to create keyring entry for credentials: {:?}.",
+            e
+        ))
+    })
+}
+
+fn read_password(
+    app: &AppHandle,
+    email: &str,
+    backend: StorageBackend,
+) -> Result<Option<String>, IloaderError> {
+    match backend {
+        StorageBackend::Keyring => match password_entry(email)?.get_password() {
+            Ok(password) => Ok(Some(password)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(IloaderError::Storage(format!(
+                "Failed to get credentials: {:?}",
+                e
+            ))),
+        },
+        StorageBackend::EncryptedFile => encrypted_storage(app, email)?
+            .retrieve(PASSWORD_KEY)
+            .map_err(|e| IloaderError::Storage(format!("Failed to get credentials: {}", e))),
+    }
+}
+
+fn write_password(
+    app: &AppHandle,
+    email: &str,
+    password: &str,
+    backend: StorageBackend,
+) -> Result<(), IloaderError> {
+    match backend {
+        StorageBackend::Keyring => password_entry(email)?.set_password(password).map_err(|e| {
+            IloaderError::Storage(format!("Failed to save credentials to keyring: {:?}", e))
+        }),
+        StorageBackend::EncryptedFile => encrypted_storage(app, email)?
+            .store(PASSWORD_KEY, password)
+            .map_err(|e| IloaderError::Storage(format!("Failed to save credentials: {}", e))),
+    }
+}
+
+fn remove_password(
+    app: &AppHandle,
+    email: &str,
+    backend: StorageBackend,
+) -> Result<(), IloaderError> {
+    match backend {
+        StorageBackend::Keyring => match password_entry(email)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(IloaderError::Storage(format!(
+                "Failed to delete credentials: {:?}",
+                e
+            ))),
+        },
+        StorageBackend::EncryptedFile => encrypted_storage(app, email)?
+            .delete(PASSWORD_KEY)
+            .map_err(|e| IloaderError::Storage(format!("Failed to delete credentials: {}", e))),
+    }
+}
+
+pub fn save_password(app: &AppHandle, email: &str, password: &str) -> Result<(), IloaderError> {
+    write_password(app, email, password, active_backend())
+}
+
+pub fn load_password(app: &AppHandle, email: &str) -> Result<String, IloaderError> {
+    read_password(app, email, active_backend())?
+        .ok_or_else(|| IloaderError::Storage(format!("No saved credentials for {}", email)))
+}
+
+pub fn delete_password(app: &AppHandle, email: &str) -> Result<(), IloaderError> {
+    remove_password(app, email, active_backend())
+}
+
+pub fn keyring_available() -> bool {
+    let cached = *KEYRING_AVAILABLE.lock().unwrap();
+    cached.unwrap_or_else(|| probe_keyring().available())
+}
+
+// Name of the platform credential store the keyring crate is built against
+fn keyring_backend_name() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "macOS Keychain"
+    } else if cfg!(target_os = "windows") {
+        "Windows Credential Manager"
+    } else if cfg!(target_os = "linux") {
+        "Secret Service (cached in keyutils)"
+    } else {
+        "Unsupported"
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageDiagnostics {
+    pub backend: StorageBackend,
+    pub keyring_backend: &'static str,
+    pub can_write: bool,
+    pub can_read: bool,
+    pub can_delete: bool,
+    pub error: Option<String>,
+}
+
+impl StorageDiagnostics {
+    fn available(&self) -> bool {
+        self.can_write && self.can_read
+    }
+}
+
+// Write, read back and delete a throwaway keyring entry, then remember whether that worked so
+// later storage lookups don't have to touch the keyring again.
+fn probe_keyring() -> StorageDiagnostics {
+    // versions before the probe cleaned up after itself left this entry behind
+    if let Ok(legacy) = Entry::new(SERVICE, "test") {
+        let _ = legacy.delete_credential();
+    }
+
+    let mut diagnostics = StorageDiagnostics {
+        backend: StorageBackend::EncryptedFile,
+        keyring_backend: keyring_backend_name(),
+        can_write: false,
+        can_read: false,
+        can_delete: false,
+        error: None,
+    };
+
+    let probe_user = format!("probe-{}", chrono::Utc::now().timestamp_millis());
+    let probe_value = format!("iloader-{}", probe_user);
+    let result = Entry::new(SERVICE, &probe_user).and_then(|entry| {
+        entry.set_password(&probe_value)?;
+        diagnostics.can_write = true;
+        // clean up even when reading back fails, so no probe entries pile up in the keyring
+        let read = entry.get_password();
+        let deleted = entry.delete_credential();
+        diagnostics.can_read = read? == probe_value;
+        deleted?;
+        diagnostics.can_delete = true;
+        Ok(())
+    });
+    if let Err(e) = result {
+        diagnostics.error = Some(e.to_string());
+    }
+
+    if diagnostics.available() {
+        diagnostics.backend = StorageBackend::Keyring;
+    }
+    *KEYRING_AVAILABLE.lock().unwrap() = Some(diagnostics.available());
+    debug!(
+        "Keyring probe: write {}, read {}, delete {}",
+        diagnostics.can_write, diagnostics.can_read, diagnostics.can_delete
+    );
+    diagnostics
+}
+
+// Re-run the keyring probe, e.g. after the user unlocked their keyring, and switch backends
+// accordingly without restarting iloader.
+#[tauri::command]
+pub fn diagnose_storage() -> StorageDiagnostics {
+    probe_keyring()
+}
+
+fn delete_anisette_state(storage: Box<dyn SideloadingStorage>) -> Result<(), IloaderError> {
+    storage
+        .delete(ANISETTE_STATE_KEY)
+        .map_err(|e| IloaderError::Storage(format!("Failed to delete anisette state: {:?}", e)))
+}
+
+// Reset the anisette state of one account. Without an email the active account is reset, along
+// with the shared entry older versions of iloader used for everyone.
+#[tauri::command]
+pub fn reset_anisette_state(
+    handle: AppHandle,
+    sideloader_state: State<'_, SideloaderMutex>,
+    email: Option<String>,
+) -> Result<(), IloaderError> {
+    let target = match email {
+        Some(email) => Some(email),
+        None => {
+            if let Err(e) = delete_anisette_state(shared_storage(&handle)) {
+                warn!("Failed to delete shared anisette state: {}", e);
+            }
+            sideloader_state.lock().unwrap().resolve(None).ok()
+        }
+    };
+
+    match target {
+        Some(email) => delete_anisette_state(storage(&handle, &email)?),
+        None => Ok(()),
+    }
+}
+
+#[tauri::command]
+pub fn list_stored_data(
+    handle: AppHandle,
+    email: String,
+) -> Result<Vec<StoredEntry>, IloaderError> {
+    let keys = match active_backend() {
+        StorageBackend::Keyring => {
+            // keyrings can't be enumerated, so only report the entries iloader knows the names of
+            let storage = storage(&handle, &email)?;
+            KNOWN_KEYS
+                .iter()
+                .filter(|(key, _)| matches!(storage.retrieve(key), Ok(Some(_))))
+                .map(|(key, _)| key.to_string())
+                .collect()
+        }
+        StorageBackend::EncryptedFile => encrypted_storage(&handle, &email)?.keys(),
+    };
+
+    Ok(keys
+        .into_iter()
+        .map(|key| StoredEntry {
+            description: KNOWN_KEYS
+                .iter()
+                .find(|(known, _)| *known == key)
+                .map(|(_, description)| description.to_string()),
+            key,
+            backend: active_backend(),
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub fn storage_backend() -> StorageBackend {
+    active_backend()
+}
+
+// Everything stored for an account except its password, with whether it's binary. Keyrings
+// can't be enumerated, so there the known entries and isideload's signing key are named.
+fn migration_keys(
+    app: &AppHandle,
+    email: &str,
+    from: StorageBackend,
+) -> Result<Vec<(String, bool)>, IloaderError> {
+    let signing_key = signing_key_key(email);
+    let keys = match from {
+        StorageBackend::EncryptedFile => encrypted_storage(app, email)?
+            .keys()
+            .into_iter()
+            .filter(|key| key != PASSWORD_KEY)
+            .collect(),
+        StorageBackend::Keyring => KNOWN_KEYS
+            .iter()
+            .map(|(key, _)| key.to_string())
+            .chain([signing_key.clone()])
+            .collect::<Vec<_>>(),
+    };
+    Ok(keys
+        .into_iter()
+        .map(|key| {
+            let binary = key == signing_key;
+            (key, binary)
+        })
+        .collect())
+}
+
+// Move an account's saved password, entries and signing key into the backend iloader is
+// currently using, e.g. after setting up a keyring on a machine that used to fall back to
+// encrypted files. Plaintext files left by versions before per-account storage are moved too.
+#[tauri::command]
+pub fn migrate_account_storage(
+    handle: AppHandle,
+    email: String,
+    to: StorageBackend,
+) -> Result<Vec<String>, IloaderError> {
+    // iloader always reads from the active backend, anything moved elsewhere would be lost
+    if to != active_backend() {
+        return Err(IloaderError::Storage(format!(
+            "Can only migrate to the storage iloader is currently using ({:?})",
+            active_backend()
+        )));
+    }
+    let from = match to {
+        StorageBackend::Keyring => StorageBackend::EncryptedFile,
+        StorageBackend::EncryptedFile => StorageBackend::Keyring,
+    };
+
+    let source = backend_storage(&handle, &email, from)?;
+    let target = backend_storage(&handle, &email, to)?;
+    let mut migrated = vec![];
+
+    if let Some(password) = read_password(&handle, &email, from)? {
+        write_password(&handle, &email, &password, to)?;
+        remove_password(&handle, &email, from)?;
+        migrated.push(PASSWORD_KEY.to_string());
+    }
+
+    for (key, binary) in migration_keys(&handle, &email, from)? {
+        let value = match read_entry(source.as_ref(), &key, binary) {
+            Ok(Some(value)) => value,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!("Failed to read {} for migration: {}", key, e);
+                continue;
+            }
+        };
+        write_entry(target.as_ref(), &key, &value, binary)
+            .map_err(|e| IloaderError::Storage(format!("Failed to migrate {}: {}", key, e)))?;
+        if let Err(e) = source.delete(&key) {
+            warn!("Failed to remove migrated {}: {}", key, e);
+        }
+        migrated.push(key);
+    }
+
+    copy_legacy_entries(&email, &LegacyFiles(data_dir(&handle)), target.as_ref());
+
+    debug!("Migrated {:?} for {} to {:?}", migrated, email, to);
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use isideload::util::storage::InMemoryStorage;
+
+    const EMAIL: &str = "Jane@Example.com";
+    const KEY_DER: &[u8] = &[0x30, 0x82, 0x04, 0xbe, 0x00, 0xff, 0x80];
+
+    fn legacy() -> InMemoryStorage {
+        let legacy = InMemoryStorage::new();
+        legacy
+            .store(ANISETTE_STATE_KEY, "{\"adi_pb\":\"shared\"}")
+            .unwrap();
+        legacy
+            .store("session_jane@example.com", "{\"adsid\":\"jane\"}")
+            .unwrap();
+        legacy.store_data(&signing_key_key(EMAIL), KEY_DER).unwrap();
+        legacy
+    }
+
+    #[test]
+    fn signing_key_matches_isideload() {
+        // sha256("jane@example.com"), the lowercased email isideload is built with
+        assert_eq!(
+            signing_key_key(EMAIL),
+            format!("{:x}/key", Sha256::digest(b"jane@example.com"))
+        );
+        assert_eq!(signing_key_key(EMAIL).len(), 64 + "/key".len());
+    }
+
+    #[test]
+    fn moves_everything_into_a_new_account() {
+        let legacy = legacy();
+        let account = InMemoryStorage::new();
+        copy_legacy_entries(EMAIL, &legacy, &account);
+
+        assert_eq!(
+            account.retrieve(ANISETTE_STATE_KEY).unwrap().as_deref(),
+            Some("{\"adi_pb\":\"shared\"}")
+        );
+        assert_eq!(
+            account.retrieve(SESSION_KEY).unwrap().as_deref(),
+            Some("{\"adsid\":\"jane\"}")
+        );
+        assert_eq!(
+            account.retrieve_data(&signing_key_key(EMAIL)).unwrap(),
+            Some(KEY_DER.to_vec())
+        );
+
+        assert_eq!(legacy.retrieve(ANISETTE_STATE_KEY).unwrap(), None);
+        assert_eq!(legacy.retrieve("session_jane@example.com").unwrap(), None);
+        assert_eq!(legacy.retrieve(&signing_key_key(EMAIL)).unwrap(), None);
+    }
+
+    #[test]
+    fn anisette_state_goes_to_the_first_account_only() {
+        let legacy = legacy();
+        let first = InMemoryStorage::new();
+        let second = InMemoryStorage::new();
+        copy_legacy_entries(EMAIL, &legacy, &first);
+        copy_legacy_entries("john@example.com", &legacy, &second);
+
+        assert!(first.retrieve(ANISETTE_STATE_KEY).unwrap().is_some());
+        assert_eq!(second.retrieve(ANISETTE_STATE_KEY).unwrap(), None);
+    }
+
+    #[test]
+    fn other_accounts_entries_stay_put() {
+        let legacy = legacy();
+        let other = InMemoryStorage::new();
+        copy_legacy_entries("john@example.com", &legacy, &other);
+
+        assert_eq!(other.retrieve(SESSION_KEY).unwrap(), None);
+        assert_eq!(other.retrieve(&signing_key_key(EMAIL)).unwrap(), None);
+        assert!(
+            legacy
+                .retrieve("session_jane@example.com")
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            legacy
+                .retrieve_data(&signing_key_key(EMAIL))
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn existing_entries_win() {
+        let legacy = legacy();
+        let account = InMemoryStorage::new();
+        account
+            .store(ANISETTE_STATE_KEY, "{\"adi_pb\":\"own\"}")
+            .unwrap();
+        copy_legacy_entries(EMAIL, &legacy, &account);
+
+        assert_eq!(
+            account.retrieve(ANISETTE_STATE_KEY).unwrap().as_deref(),
+            Some("{\"adi_pb\":\"own\"}")
+        );
+        assert!(legacy.retrieve(ANISETTE_STATE_KEY).unwrap().is_some());
+    }
+
+    #[test]
+    fn plaintext_files_are_removed_not_emptied() {
+        let dir = std::env::temp_dir().join(format!(
+            "iloader-legacy-{}",
+            chrono::Utc::now().timestamp_nanos_opt().unwrap()
+        ));
+        let files = LegacyFiles(dir.clone());
+        files.store(ANISETTE_STATE_KEY, "state").unwrap();
+        files.store_data(&signing_key_key(EMAIL), KEY_DER).unwrap();
+
+        let account = InMemoryStorage::new();
+        copy_legacy_entries(EMAIL, &files, &account);
+
+        // FsStorage keeps binary data as is, so the key must come across byte for byte
+        assert_eq!(
+            account.retrieve_data(&signing_key_key(EMAIL)).unwrap(),
+            Some(KEY_DER.to_vec())
+        );
+        assert!(!dir.join(ANISETTE_STATE_KEY).exists());
+        assert!(!dir.join(signing_key_key(EMAIL)).exists());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,200 @@
+use isideload::dev::teams::{DeveloperTeam, TeamsApi};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Listener, State, Window};
+use tauri_plugin_store::StoreExt;
+use tracing::warn;
+
+use crate::{
+    account::rebuild_sideloader,
+    error::IloaderError,
+    sideload::{SideloaderGuard, SideloaderMutex},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamInfo {
+    pub name: Option<String>,
+    pub team_id: String,
+    pub team_type: Option<String>,
+    pub status: Option<String>,
+    pub selected: bool,
+}
+
+impl TeamInfo {
+    fn new(team: &DeveloperTeam, selected: Option<&str>) -> Self {
+        TeamInfo {
+            name: team.name.clone(),
+            team_id: team.team_id.clone(),
+            team_type: team.r#type.clone(),
+            status: team.status.clone(),
+            selected: selected == Some(team.team_id.as_str()),
+        }
+    }
+}
+
+// chosen teams are kept in data.json as a map of account email -> team id
+pub fn saved_team_id(handle: &AppHandle, email: &str) -> Option<String> {
+    let store = handle.store("data.json").ok()?;
+    store
+        .get("teams")?
+        .get(email.to_lowercase())?
+        .as_str()
+        .map(|s| s.to_string())
+}
+
+pub fn save_team_id(handle: &AppHandle, email: &str, team_id: &str) -> Result<(), IloaderError> {
+    let store = handle
+        .store("data.json")
+        .map_err(|e| IloaderError::Storage(format!("Failed to get store: {:?}", e)))?;
+    let mut teams = store
+        .get("teams")
+        .and_then(|v| v.as_object().cloned())
+        .unwrap_or_else(Map::new);
+    teams.insert(email.to_lowercase(), Value::String(team_id.to_string()));
+    store.set("teams", Value::Object(teams));
+    Ok(())
+}
+
+// Called by the sideloader whenever it needs to pick a team. A previously chosen team is reused
+// if the account still belongs to it, otherwise the frontend is asked to choose one.
+pub fn team_selection_callback(
+    app: &AppHandle,
+    window: &Window,
+    email: &str,
+) -> impl Fn(&Vec<DeveloperTeam>) -> Option<String> + Send + Sync + 'static {
+    let app = app.clone();
+    let window = window.clone();
+    let email = email.to_lowercase();
+    move |teams: &Vec<DeveloperTeam>| -> Option<String> {
+        if let Some(team_id) = saved_team_id(&app, &email) {
+            if teams.iter().any(|t| t.team_id == team_id) {
+                return Some(team_id);
+            }
+            warn!(
+                "Saved team {} is no longer available for {}",
+                team_id, email
+            );
+        }
+
+        if teams.len() == 1 {
+            return teams.first().map(|t| t.team_id.clone());
+        }
+
+        let team_infos: Vec<TeamInfo> = teams.iter().map(|t| TeamInfo::new(t, None)).collect();
+        window
+            .emit("team-selection-required", team_infos)
+            .expect("Failed to emit team-selection-required event");
+
+        let (tx, rx) = std::sync::mpsc::channel::<Option<String>>();
+        let handler_id = window.listen("team-selection-response", move |event| {
+            let team_id = serde_json::from_str::<Option<String>>(event.payload()).unwrap_or(None);
+            let _ = tx.send(team_id);
+        });
+
+        let result = rx.recv_timeout(Duration::from_secs(300));
+        window.unlisten(handler_id);
+
+        let team_id = result.unwrap_or(None)?;
+        if let Err(e) = save_team_id(&app, &email, &team_id) {
+            warn!("Failed to save selected team: {}", e);
+        }
+        Some(team_id)
+    }
+}
+
+// The team every account-scoped developer API call should use: the saved choice if there is one,
+// otherwise whatever the sideloader picks.
+pub async fn selected_team(
+    handle: &AppHandle,
+    sideloader: &mut SideloaderGuard<'_>,
+) -> Result<DeveloperTeam, IloaderError> {
+    if let Some(team_id) = saved_team_id(handle, sideloader.email()) {
+        let teams = sideloader
+            .get_mut()
+            .get_dev_session()
+            .list_teams()
+            .await
+            .map_err(|e| IloaderError::DeveloperApi(format!("Failed to list teams: {:?}.", e)))?;
+        if let Some(team) = teams.into_iter().find(|t| t.team_id == team_id) {
+            return Ok(team);
+        }
+        warn!(
+            "Saved team {} is no longer available for {}",
+            team_id,
+            sideloader.email()
+        );
+    }
+
+    sideloader
+        .get_mut()
+        .get_team()
+        .await
+        .map_err(|e| IloaderError::developer(e.to_string()))
+}
+
+#[tauri::command]
+pub async fn list_teams(
+    handle: AppHandle,
+    sideloader_state: State<'_, SideloaderMutex>,
+    email: Option<String>,
+) -> Result<Vec<TeamInfo>, IloaderError> {
+    let mut sideloader = SideloaderGuard::take(&sideloader_state, email.as_deref())?;
+    let selected = saved_team_id(&handle, sideloader.email());
+
+    let teams = sideloader
+        .get_mut()
+        .get_dev_session()
+        .list_teams()
+        .await
+        .map_err(|e| IloaderError::DeveloperApi(format!("Failed to list teams: {:?}.", e)))?;
+
+    Ok(teams
+        .iter()
+        .map(|t| TeamInfo::new(t, selected.as_deref()))
+        .collect())
+}
+
+// Returns whether the account has to log in again before the new team is used
+#[tauri::command]
+pub async fn set_team(
+    handle: AppHandle,
+    window: Window,
+    sideloader_state: State<'_, SideloaderMutex>,
+    team_id: String,
+    email: Option<String>,
+) -> Result<bool, IloaderError> {
+    let email = {
+        let mut sideloader = SideloaderGuard::take(&sideloader_state, email.as_deref())?;
+
+        let teams = sideloader
+            .get_mut()
+            .get_dev_session()
+            .list_teams()
+            .await
+            .map_err(|e| IloaderError::DeveloperApi(format!("Failed to list teams: {:?}.", e)))?;
+        if !teams.iter().any(|t| t.team_id == team_id) {
+            return Err(IloaderError::DeveloperApi(format!(
+                "Account is not a member of team {}",
+                team_id
+            )));
+        }
+
+        save_team_id(&handle, sideloader.email(), &team_id)?;
+        sideloader.email().to_string()
+    };
+
+    // the sideloader picks its team once when it's built and signs everything with that team, so
+    // it has to be replaced for the choice to apply
+    match rebuild_sideloader(&handle, &window, &email).await? {
+        Some(sideloader) => {
+            sideloader_state.lock().unwrap().replace(email, sideloader);
+            Ok(false)
+        }
+        None => {
+            sideloader_state.lock().unwrap().remove(&email);
+            Ok(true)
+        }
+    }
+}
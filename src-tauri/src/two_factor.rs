@@ -0,0 +1,227 @@
+use std::time::Duration;
+
+use isideload::auth::apple_account::{AppleAccount, LoginState};
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, EventId, Listener, Window};
+use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+use tracing::debug;
+
+use crate::error::IloaderError;
+
+pub const DEFAULT_TIMEOUT_SECS: u64 = 120;
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum TwoFactorMethod {
+    TrustedDevice,
+    Sms,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustedPhoneNumber {
+    pub id: u32,
+    pub number: String,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct TwoFactorPrompt {
+    method: TwoFactorMethod,
+    phone_id: Option<u32>,
+    phone_numbers: Vec<TrustedPhoneNumber>,
+    timeout_secs: u64,
+    error: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum TwoFactorResponse {
+    Code {
+        code: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Sms {
+        phone_id: u32,
+    },
+    TrustedDevice,
+    Resend,
+    Cancel,
+}
+
+// Listens for the frontend's answers to a 2FA prompt for as long as it is alive
+pub struct TwoFactorExchange {
+    window: Window,
+    timeout: Duration,
+    rx: UnboundedReceiver<TwoFactorResponse>,
+    handler_ids: [EventId; 2],
+}
+
+impl TwoFactorExchange {
+    pub fn new(window: &Window, timeout_secs: Option<u64>) -> Self {
+        let (tx, rx) = unbounded_channel();
+        let legacy_tx = tx.clone();
+        let response_id = window.listen("2fa-response", move |event| {
+            match serde_json::from_str::<TwoFactorResponse>(event.payload()) {
+                Ok(response) => {
+                    let _ = tx.send(response);
+                }
+                Err(e) => debug!("Ignoring malformed 2fa-response: {}", e),
+            }
+        });
+        // older frontends only ever send the code itself
+        let legacy_id = window.listen("2fa-recieved", move |event| {
+            let code = event.payload().trim_matches('"').to_string();
+            let _ = legacy_tx.send(TwoFactorResponse::Code { code });
+        });
+
+        TwoFactorExchange {
+            window: window.clone(),
+            timeout: Duration::from_secs(timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS)),
+            rx,
+            handler_ids: [response_id, legacy_id],
+        }
+    }
+
+    async fn prompt(
+        &mut self,
+        method: TwoFactorMethod,
+        phone_id: Option<u32>,
+        phone_numbers: &[TrustedPhoneNumber],
+        error: Option<String>,
+    ) -> Result<TwoFactorResponse, IloaderError> {
+        self.window
+            .emit(
+                "2fa-required",
+                TwoFactorPrompt {
+                    method,
+                    phone_id,
+                    phone_numbers: phone_numbers.to_vec(),
+                    timeout_secs: self.timeout.as_secs(),
+                    error,
+                },
+            )
+            .map_err(|_| IloaderError::Other("Failed to emit status to frontend".to_string()))?;
+
+        match tokio::time::timeout(self.timeout, self.rx.recv()).await {
+            Ok(Some(TwoFactorResponse::Cancel)) | Ok(None) => Err(IloaderError::Cancelled(
+                "Two-factor authentication was cancelled".to_string(),
+            )),
+            Ok(Some(response)) => Ok(response),
+            Err(_) => Err(IloaderError::TwoFactor(format!(
+                "Two-factor authentication timed out after {} seconds without a code",
+                self.timeout.as_secs()
+            ))),
+        }
+    }
+}
+
+impl Drop for TwoFactorExchange {
+    fn drop(&mut self) {
+        for id in self.handler_ids {
+            self.window.unlisten(id);
+        }
+    }
+}
+
+// Drive the account through two-factor authentication, letting the frontend switch between
+// trusted-device push and SMS, resend codes or cancel until a code is accepted.
+pub async fn complete_two_factor(
+    account: &mut AppleAccount,
+    mut state: LoginState,
+    exchange: &mut TwoFactorExchange,
+) -> Result<(), IloaderError> {
+    let phone_numbers: Vec<TrustedPhoneNumber> = account
+        .get_auth_extras()
+        .await
+        .map(|extras| {
+            extras
+                .trusted_phone_numbers
+                .iter()
+                .map(|p| TrustedPhoneNumber {
+                    id: p.id,
+                    number: p.number_with_dial_code.clone(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut method = TwoFactorMethod::TrustedDevice;
+    let mut phone_id = None;
+    let mut sms_body = None;
+    let mut error = None;
+
+    loop {
+        match state {
+            LoginState::LoggedIn => return Ok(()),
+            LoginState::NeedsDevice2FA => {
+                method = TwoFactorMethod::TrustedDevice;
+                phone_id = None;
+                state = account.send_2fa_to_devices().await.map_err(|e| {
+                    IloaderError::TwoFactor(format!(
+                        "Failed to send code to trusted devices: {}",
+                        e
+                    ))
+                })?;
+                continue;
+            }
+            LoginState::NeedsSMS2FA => {
+                let id = phone_id
+                    .or_else(|| phone_numbers.first().map(|p| p.id))
+                    .unwrap_or(1);
+                method = TwoFactorMethod::Sms;
+                phone_id = Some(id);
+                state = account.send_sms_2fa_to_devices(id).await.map_err(|e| {
+                    IloaderError::TwoFactor(format!("Failed to send SMS code: {}", e))
+                })?;
+                continue;
+            }
+            LoginState::Needs2FAVerification => sms_body = None,
+            LoginState::NeedsSMS2FAVerification(body) => sms_body = Some(body),
+            other => {
+                return Err(IloaderError::Auth(format!(
+                    "Unexpected login state: {:?}",
+                    other
+                )));
+            }
+        }
+
+        let response = exchange
+            .prompt(method, phone_id, &phone_numbers, error.take())
+            .await?;
+
+        state = match response {
+            TwoFactorResponse::Code { code } => {
+                let result = match sms_body.clone() {
+                    Some(body) => account.verify_sms_2fa(code, body).await,
+                    None => account.verify_2fa(code).await,
+                };
+                match result {
+                    Ok(state) => state,
+                    Err(e) => {
+                        error = Some(format!("Incorrect verification code: {}", e));
+                        state = match sms_body.take() {
+                            Some(body) => LoginState::NeedsSMS2FAVerification(body),
+                            None => LoginState::Needs2FAVerification,
+                        };
+                        continue;
+                    }
+                }
+            }
+            TwoFactorResponse::Sms { phone_id: id } => {
+                phone_id = Some(id);
+                LoginState::NeedsSMS2FA
+            }
+            TwoFactorResponse::TrustedDevice => LoginState::NeedsDevice2FA,
+            TwoFactorResponse::Resend => match phone_id {
+                Some(_) => LoginState::NeedsSMS2FA,
+                None => LoginState::NeedsDevice2FA,
+            },
+            TwoFactorResponse::Cancel => {
+                return Err(IloaderError::Cancelled(
+                    "Two-factor authentication was cancelled".to_string(),
+                ));
+            }
+        };
+    }
+}
@@ -28,7 +28,7 @@ import { GlassCard } from "./components/GlassCard";
 import { useTranslation } from "react-i18next";
 
 function App() {
-  const { t } = useTranslation();
+  const { t, i18n } = useTranslation();
 
   const [operationState, setOperationState] = useState<OperationState | null>(
     null,
@@ -56,6 +56,19 @@ function App() {
     checkForUpdates();
   }, []);
 
+  // the tray menu is native, so it gets its text in the current language from here
+  useEffect(() => {
+    invoke("set_tray_labels", {
+      labels: {
+        show: t("tray.show"),
+        refresh: t("tray.refresh"),
+        quit: t("tray.quit"),
+      },
+    }).catch((error) => {
+      console.error("Failed to update tray labels", error);
+    });
+  }, [t, i18n.language]);
+
   useEffect(() => {
     if (typeof navigator === "undefined") return;
     const ua = navigator.userAgent || "";
@@ -87,6 +100,7 @@ function App() {
         started: [],
         failed: [],
         completed: [],
+        progress: [],
       });
       return new Promise<void>(async (resolve, reject) => {
         const unlistenFn = await listen<OperationUpdate>(
@@ -104,6 +118,17 @@ function App() {
                   ...old,
                   completed: [...old.completed, event.payload.stepId],
                 };
+              } else if (event.payload.updateType === "progress") {
+                return {
+                  ...old,
+                  progress: [
+                    ...old.progress,
+                    {
+                      stepId: event.payload.stepId,
+                      details: event.payload.extraDetails,
+                    },
+                  ],
+                };
               } else if (event.payload.updateType === "failed") {
                 return {
                   ...old,
@@ -112,6 +137,7 @@ function App() {
                     {
                       stepId: event.payload.stepId,
                       extraDetails: event.payload.extraDetails,
+                      diagnosis: event.payload.diagnosis,
                     },
                   ],
                 };
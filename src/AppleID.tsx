@@ -25,16 +25,19 @@ export const AppleID = ({
   const [emailInput, setEmailInput] = useState<string>("");
   const [passwordInput, setPasswordInput] = useState<string>("");
   const [saveCredentials, setSaveCredentials] = useState<boolean>(false);
-  const [tfaOpen, setTfaOpen] = useState<boolean>(false);
+  const [tfaPrompt, setTfaPrompt] = useState<TwoFactorPrompt | null>(null);
   const [tfaCode, setTfaCode] = useState<string>("");
   const [addAccountOpen, setAddAccountOpen] = useState<boolean>(false);
   const [anisetteServer] = useStore<string>(
     "anisetteServer",
     "ani.sidestore.io",
   );
+  const [twoFactorTimeout] = useStore<number>("twoFactorTimeout", 120);
+  const [anisetteFallbacks] = useStore<string[]>("anisetteFallbacks", []);
   const [certs, setCerts] = useState<Certificate[] | null>(null);
   const [selectedSerials, setSelectedSerials] = useState<string[]>([]);
   const [chooseCertsOpen, setChooseCertsOpen] = useState<boolean>(false);
+  const [teams, setTeams] = useState<Team[] | null>(null);
   const { err } = useError();
 
   useEffect(() => {
@@ -61,9 +64,13 @@ export const AppleID = ({
   useEffect(() => {
     if (!listenerAdded.current) {
       (async () => {
-        const unlistenFn = await listen("2fa-required", () => {
-          setTfaOpen(true);
-        });
+        const unlistenFn = await listen<TwoFactorPrompt>(
+          "2fa-required",
+          (prompt) => {
+            setTfaPrompt(prompt.payload);
+            if (prompt.payload.error) toast.error(prompt.payload.error);
+          },
+        );
         unlisten.current = unlistenFn;
       })();
       listenerAdded.current = true;
@@ -94,6 +101,27 @@ export const AppleID = ({
     };
   }, []);
 
+  const teamListenerAdded = useRef<boolean>(false);
+  const teamUnlisten = useRef<() => void>(() => { });
+
+  useEffect(() => {
+    if (!teamListenerAdded.current) {
+      (async () => {
+        const unlistenFn = await listen<Team[]>(
+          "team-selection-required",
+          (teams) => {
+            setTeams(teams.payload);
+          },
+        );
+        teamUnlisten.current = unlistenFn;
+      })();
+      teamListenerAdded.current = true;
+    }
+    return () => {
+      teamUnlisten.current();
+    };
+  }, []);
+
   return (
     <>
       <h2 style={{ marginTop: 0 }}>{t("apple_id.title")}</h2>
@@ -139,9 +167,11 @@ export const AppleID = ({
                         className="action-button primary"
                         onClick={() => {
                           let promise = async () => {
-                            await invoke("login_stored", {
+                            await invoke("resume_session", {
                               email: id,
                               anisetteServer,
+                              twoFactorTimeout,
+                              anisetteFallbacks,
                             });
                             setForceUpdateIds((v) => v + 1);
                           };
@@ -230,6 +260,8 @@ export const AppleID = ({
                         password: passwordInput,
                         saveCredentials: saveCredentials,
                         anisetteServer,
+                        twoFactorTimeout,
+                        anisetteFallbacks,
                       });
                       setForceUpdateIds((v) => v + 1);
                     };
@@ -255,9 +287,17 @@ export const AppleID = ({
             </div>
           )}
       </div>
-      <Modal sizeFit isOpen={tfaOpen} zIndex={2000}>
+      <Modal sizeFit isOpen={tfaPrompt !== null} zIndex={2000}>
         <h2>{t("apple_id.two_factor_title")}</h2>
-        <p>{t("apple_id.two_factor_prompt")}</p>
+        <p>
+          {tfaPrompt?.method === "sms"
+            ? t("apple_id.two_factor_sms_prompt", {
+                number:
+                  tfaPrompt.phoneNumbers.find((p) => p.id === tfaPrompt.phoneId)
+                    ?.number ?? "",
+              })
+            : t("apple_id.two_factor_prompt")}
+        </p>
         <input
           type="text"
           placeholder={t("apple_id.verification_placeholder")}
@@ -271,13 +311,61 @@ export const AppleID = ({
               toast.warning(t("apple_id.valid_6digit"));
               return;
             }
-            await emit("2fa-recieved", tfaCode);
-            setTfaOpen(false);
+            await emit("2fa-response", { action: "code", code: tfaCode });
+            setTfaPrompt(null);
             setTfaCode("");
           }}
         >
           {t("apple_id.submit")}
         </button>
+        <div className="certs-buttons">
+          <button
+            className="action-button"
+            onClick={async () => {
+              await emit("2fa-response", { action: "resend" });
+              setTfaPrompt(null);
+            }}
+          >
+            {t("apple_id.two_factor_resend")}
+          </button>
+          {tfaPrompt?.method === "sms" ? (
+            <button
+              className="action-button"
+              onClick={async () => {
+                await emit("2fa-response", { action: "trustedDevice" });
+                setTfaPrompt(null);
+              }}
+            >
+              {t("apple_id.two_factor_use_device")}
+            </button>
+          ) : (
+            tfaPrompt?.phoneNumbers.map((phone) => (
+              <button
+                key={phone.id}
+                className="action-button"
+                onClick={async () => {
+                  await emit("2fa-response", {
+                    action: "sms",
+                    phoneId: phone.id,
+                  });
+                  setTfaPrompt(null);
+                }}
+              >
+                {t("apple_id.two_factor_send_sms", { number: phone.number })}
+              </button>
+            ))
+          )}
+          <button
+            className="action-button danger"
+            onClick={async () => {
+              await emit("2fa-response", { action: "cancel" });
+              setTfaPrompt(null);
+              setTfaCode("");
+            }}
+          >
+            {t("common.cancel")}
+          </button>
+        </div>
       </Modal>
       <Modal sizeFit isOpen={certs !== null} zIndex={2000}>
         <h2 className="cert-header">{t("apple_id.max_certs_title")}</h2>
@@ -350,6 +438,52 @@ export const AppleID = ({
           </button>
         </div>
       </Modal>
+      <Modal sizeFit isOpen={teams !== null} zIndex={2000}>
+        <h2 className="cert-header">{t("apple_id.select_team_title")}</h2>
+        <p className="certs-desc">{t("apple_id.select_team_desc")}</p>
+        <div className="certs-list">
+          {teams?.map((team) => (
+            <button
+              key={team.teamId}
+              className="action-button primary"
+              onClick={async () => {
+                await emit("team-selection-response", team.teamId);
+                setTeams(null);
+              }}
+            >
+              {team.name ?? team.teamId}
+              {team.teamType ? ` (${team.teamType})` : ""}
+            </button>
+          ))}
+        </div>
+        <div className="certs-buttons">
+          <button
+            className="action-button danger"
+            onClick={async () => {
+              await emit("team-selection-response", null);
+              setTeams(null);
+            }}
+          >
+            {t("common.cancel")}
+          </button>
+        </div>
+      </Modal>
     </>
   );
 };
+
+type TwoFactorPrompt = {
+  method: "trustedDevice" | "sms";
+  phoneId: number | null;
+  phoneNumbers: { id: number; number: string }[];
+  timeoutSecs: number;
+  error: string | null;
+};
+
+type Team = {
+  name: string | null;
+  teamId: string;
+  teamType: string | null;
+  status: string | null;
+  selected: boolean;
+};
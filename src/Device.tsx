@@ -3,6 +3,7 @@ import "./Device.css";
 import { invoke } from "@tauri-apps/api/core";
 import { toast } from "sonner";
 import { useTranslation } from "react-i18next";
+import { errorMessage } from "./ErrorContext";
 
 export type DeviceInfo = {
   name: string;
@@ -29,7 +30,7 @@ export const Device = ({
     (device: DeviceInfo | null) => {
       setSelectedDevice(device);
       invoke("set_selected_device", { device }).catch((err) => {
-        toast.error(t("device.failed_select_prefix") + err);
+        toast.error(t("device.failed_select_prefix") + errorMessage(err));
       });
     },
     [setSelectedDevice, t],
@@ -61,7 +62,7 @@ export const Device = ({
         }
         return count > 1 ? t("device.found_devices") : t("device.found_device");
       },
-      error: (e) => t("device.unable_load_devices_prefix") + e,
+      error: (e) => t("device.unable_load_devices_prefix") + errorMessage(e),
     });
   }, [setDevices, selectDevice, t]);
   useEffect(() => {
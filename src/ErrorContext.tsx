@@ -5,8 +5,24 @@ import { toast } from "sonner";
 import { openUrl } from "@tauri-apps/plugin-opener";
 import { Trans, useTranslation } from "react-i18next";
 
+export type IloaderError = {
+  code: string;
+  message: string;
+  hint: string | null;
+};
+
+// commands reject with an IloaderError, but older code paths and plugins still reject with strings
+export const errorMessage = (err: unknown): string | null => {
+  if (err === null || err === undefined) return null;
+  if (typeof err === "object" && "message" in err && "code" in err) {
+    const e = err as IloaderError;
+    return `[${e.code}] ${e.message}` + (e.hint ? `\n\n${e.hint}` : "");
+  }
+  return String(err);
+};
+
 export const ErrorContext = createContext<{
-  err: (msg: string, err: string | null) => string;
+  err: (msg: string, err: unknown) => string;
 }>({ err: () => "" });
 
 export const ErrorProvider: React.FC<{ children: React.ReactNode }> = ({
@@ -34,9 +50,9 @@ export const ErrorProvider: React.FC<{ children: React.ReactNode }> = ({
   return (
     <ErrorContext.Provider
       value={{
-        err: (msg: string, err: string | null) => {
+        err: (msg: string, err: unknown) => {
           setMsg(msg);
-          setError(err);
+          setError(errorMessage(err));
           setMoreDetailsOpen(false);
           return msg;
         },
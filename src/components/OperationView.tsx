@@ -87,12 +87,29 @@ export default ({
                 </div>
 
                 <div className="operation-step-internal">
-                  <p>{t(step.titleKey)}</p>
+                  <p>{t(step.titleKey, step.titleParams)}</p>
+                  {operationState.progress
+                    .filter((p) => p.stepId == step.id)
+                    .map((p, i) => (
+                      <p className="operation-progress" key={i}>
+                        {p.details}
+                      </p>
+                    ))}
                   {failed && (
                     <>
                       <pre className="operation-extra-details">
                         {!errorShort ? failed.extraDetails.replace(/^\n+/, "") : errorShort}
                       </pre>
+                      {failed.diagnosis && (
+                        <div className="operation-diagnosis">
+                          <p>{failed.diagnosis.cause}</p>
+                          <ol>
+                            {failed.diagnosis.steps.map((s) => (
+                              <li key={s}>{s}</li>
+                            ))}
+                          </ol>
+                        </div>
+                      )}
                       {errorShort !== "" && errorShort !== null && errorShort !== undefined &&
                         <>
                           <p
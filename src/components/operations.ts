@@ -9,6 +9,7 @@ export type Operation = {
 export type OperationStep = {
   id: string;
   titleKey: string;
+  titleParams?: Record<string, string>;
 };
 
 export type OperationState = {
@@ -18,21 +19,42 @@ export type OperationState = {
   failed: {
     stepId: string;
     extraDetails: string;
+    diagnosis: Diagnosis | null;
+  }[];
+  progress: {
+    stepId: string;
+    details: string;
   }[];
 };
 
+export type Diagnosis = {
+  cause: string;
+  steps: string[];
+  docKey: string;
+};
+
 type OperationInfoUpdate = {
   updateType: "started" | "finished";
   stepId: string;
 };
 
+type OperationProgressUpdate = {
+  updateType: "progress";
+  stepId: string;
+  extraDetails: string;
+};
+
 type OperationFailedUpdate = {
   updateType: "failed";
   stepId: string;
   extraDetails: string;
+  diagnosis: Diagnosis | null;
 };
 
-export type OperationUpdate = OperationInfoUpdate | OperationFailedUpdate;
+export type OperationUpdate =
+  | OperationInfoUpdate
+  | OperationProgressUpdate
+  | OperationFailedUpdate;
 
 export const installSideStoreOperation: Operation = {
   id: "install_sidestore",
@@ -86,3 +108,33 @@ export const sideloadOperation = {
     },
   ],
 };
+
+export const revokeCertificatesOperation: Operation = {
+  id: "revoke_certificates",
+  titleKey: "operations.revoke_certificates_title",
+  successMessageKey: "operations.revoke_certificates_success_message",
+  steps: [
+    {
+      id: "select",
+      titleKey: "operations.revoke_certificates_step_select",
+    },
+    {
+      id: "revoke",
+      titleKey: "operations.revoke_certificates_step_revoke",
+    },
+  ],
+};
+
+// One step per app, identified by the app's bundle ID
+export const refreshAppsOperation = (
+  apps: { bundleId: string; name?: string | null }[],
+): Operation => ({
+  id: "refresh_apps",
+  titleKey: "operations.refresh_apps_title",
+  successMessageKey: "operations.refresh_apps_success_message",
+  steps: apps.map((app) => ({
+    id: app.bundleId,
+    titleKey: "operations.refresh_apps_step_refresh",
+    titleParams: { name: app.name ?? app.bundleId },
+  })),
+});
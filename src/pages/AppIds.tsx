@@ -12,6 +12,7 @@ type AppId = {
   name: string;
   features: Record<string, any>;
   expirationDate: string | null;
+  installed: boolean | null;
 };
 
 type AppIdsResponse = {
@@ -113,7 +114,10 @@ export const AppIds = () => {
                       (i === appIds.length - 1 ? " cert-item-last" : "")
                     }
                   >
-                    <td className="cert-item-part">{appId.name}</td>
+                    <td className="cert-item-part">
+                      {appId.name}
+                      {appId.installed && ` (${t("app_ids.installed")})`}
+                    </td>
                     <td className="cert-item-part">
                       {appId.expirationDate
                         ? new Date(appId.expirationDate).toLocaleDateString()
@@ -11,8 +11,19 @@ export type Certificate = {
   serialNumber: string;
   machineName: string;
   machineId: string;
+  local: boolean;
+  createdAt: number | null;
+  expiresAt: number | null;
+  certificateType: string | null;
+  createdByIloader: boolean;
 };
 
+const EXPIRY_WARNING_DAYS = 7;
+
+const expiresSoon = (cert: Certificate) =>
+  cert.expiresAt !== null &&
+  cert.expiresAt * 1000 - Date.now() < EXPIRY_WARNING_DAYS * 24 * 60 * 60 * 1000;
+
 export const Certificates = () => {
   const { t } = useTranslation();
   const [certificates, setCertificates] = useState<Certificate[]>([]);
@@ -71,6 +82,7 @@ export const Certificates = () => {
                   <th className="cert-item-part">{t("certificates.serial_number")}</th>
                   <th className="cert-item-part">{t("certificates.machine_name")}</th>
                   <th className="cert-item-part">{t("certificates.machine_id")}</th>
+                  <th className="cert-item-part">{t("certificates.expires")}</th>
                   <th>{t("certificates.revoke")}</th>
                 </tr>
               </thead>
@@ -83,10 +95,28 @@ export const Certificates = () => {
                       (i === certificates.length - 1 ? " cert-item-last" : "")
                     }
                   >
-                    <td className="cert-item-part">{cert.name}</td>
+                    <td className="cert-item-part">
+                      {cert.name}
+                      {cert.local && ` (${t("certificates.this_computer")})`}
+                    </td>
                     <td className="cert-item-part">{cert.serialNumber}</td>
                     <td className="cert-item-part">{cert.machineName}</td>
                     <td className="cert-item-part">{cert.machineId}</td>
+                    <td
+                      className={
+                        "cert-item-part" +
+                        (expiresSoon(cert) ? " cert-expiring" : "")
+                      }
+                      title={
+                        expiresSoon(cert)
+                          ? t("certificates.expiring_soon")
+                          : undefined
+                      }
+                    >
+                      {cert.expiresAt !== null
+                        ? new Date(cert.expiresAt * 1000).toLocaleDateString()
+                        : "-"}
+                    </td>
                     <td
                       className="cert-item-revoke"
                       role="button"